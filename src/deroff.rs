@@ -1,26 +1,200 @@
 /// A translation of https://github.com/fish-shell/fish-shell/blob/e7bfd1d71ca54df726a4f1ea14bd6b0957b75752/share/tools/deroff.py
 /// Deroff, ported from deroff.py, which is ported from the venerable deroff.c
 use flate2::read::GzDecoder;
-use regex::Regex;
+use regex::{Captures, Regex};
 
+use crate::char_len;
 use crate::util::TranslationTable;
 use std::borrow::Cow;
 use std::cell::Cell;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, Read};
+use std::ops::Range;
 
-const SKIP_HEADERS: bool = false;
+/// How deep strings may refer to other strings
+const MAX_STRING_DEPTH: u8 = 32;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum TblState {
     Options,
     Format,
     Data,
+    /// Inside a `T{ ... T}` block, which may span several lines
+    TextBlock {
+        empty: bool,
+    },
+}
+
+/// The fonts told apart when keeping font annotations. In man pages, option
+/// names are set in bold and the placeholders for their arguments in italics.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Font {
+    Roman,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl Font {
+    /// Look up a font by the name used in `\fB`, `\f(CW`, `\f[BI]` or `.BR`.
+    fn from_name(name: &str) -> Option<Font> {
+        Some(match name {
+            "R" | "1" | "C" | "CR" | "CW" => Font::Roman,
+            "B" | "3" | "CB" => Font::Bold,
+            "I" | "2" | "CI" => Font::Italic,
+            "BI" | "4" | "CBI" => Font::BoldItalic,
+            _ => return None,
+        })
+    }
+
+    /// The escape written to the output when switching to this font.
+    fn escape(self) -> &'static str {
+        match self {
+            Font::Roman => "\\fR",
+            Font::Bold => "\\fB",
+            Font::Italic => "\\fI",
+            Font::BoldItalic => "\\f(BI",
+        }
+    }
+}
+
+/// An error in the input that stopped the deroffer.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DeroffError {
+    /// The line of the input, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DeroffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for DeroffError {}
+
+type MdocArgs = std::iter::Peekable<std::vec::IntoIter<String>>;
+
+/// The mdoc macros that can be called from the arguments of another.
+const MDOC_CALLABLE: &[&str] = &[
+    "Ad", "Aq", "Ar", "Bq", "Cm", "Dq", "Dv", "Em", "Er", "Ev", "Fl", "Ic", "Li", "Nm", "No", "Ns",
+    "Oc", "Oo", "Op", "Pa", "Pq", "Ql", "Qq", "Sq", "Sx", "Sy", "Ta", "Va", "Xc", "Xo", "Xr",
+];
+
+fn is_mdoc_macro(arg: &str) -> bool {
+    MDOC_CALLABLE.contains(&arg)
+}
+
+/// Punctuation that ends the arguments of a macro. Apart from `|`, mdoc sets
+/// it without a space on the side of the word it belongs to.
+fn is_punct(arg: &str) -> bool {
+    arg == "|" || is_opening_punct(arg) || is_closing_punct(arg)
+}
+
+fn is_opening_punct(arg: &str) -> bool {
+    matches!(arg, "(" | "[")
+}
+
+fn is_closing_punct(arg: &str) -> bool {
+    matches!(arg, "." | "," | ":" | ";" | ")" | "]" | "?" | "!")
+}
+
+/// The text of an mdoc line as it is being rendered.
+#[derive(Default)]
+struct MdocLine {
+    text: String,
+    /// Whether the next word goes right after the previous one
+    nospace: bool,
+}
+
+impl MdocLine {
+    fn word(&mut self, word: &str) {
+        if !self.nospace && !is_closing_punct(word) && !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(word);
+        self.nospace = is_opening_punct(word);
+    }
+}
+
+/// Split text carrying font annotations into runs of a single font. Font
+/// escapes we do not know are dropped.
+pub fn font_spans(s: &str) -> Vec<(Font, String)> {
+    let mut spans = Vec::new();
+    let (mut font, mut previous) = (Font::Roman, Font::Roman);
+    let mut text = String::new();
+    let mut rest = s;
+
+    while let Some(idx) = rest.find("\\f") {
+        text.push_str(&rest[..idx]);
+        rest = &rest[idx + 2..];
+
+        let name = if let Some(name) = rest.strip_prefix('(') {
+            let end = name.char_indices().nth(2).map_or(name.len(), |(i, _)| i);
+            rest = &name[end..];
+            &name[..end]
+        } else if let Some(name) = rest.strip_prefix('[') {
+            let end = name.find(']').unwrap_or(name.len());
+            rest = name.get(end + 1..).unwrap_or_default();
+            &name[..end]
+        } else {
+            let end = rest.chars().next().map_or(0, char::len_utf8);
+            let name = &rest[..end];
+            rest = &rest[end..];
+            name
+        };
+
+        let new_font = match name {
+            "P" | "" => previous,
+            name => match Font::from_name(name) {
+                Some(new_font) => new_font,
+                None => continue,
+            },
+        };
+        if new_font != font {
+            if !text.is_empty() {
+                spans.push((font, std::mem::take(&mut text)));
+            }
+            previous = font;
+            font = new_font;
+        }
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        spans.push((font, text));
+    }
+    spans
+}
+
+#[test]
+fn test_font_spans() {
+    assert_eq!(font_spans(""), vec![]);
+    assert_eq!(font_spans("plain"), vec![(Font::Roman, "plain".to_owned())]);
+    assert_eq!(
+        font_spans(r"\fB-o\fR, \fB--output\fR=\fIFILE\fP"),
+        vec![
+            (Font::Bold, "-o".to_owned()),
+            (Font::Roman, ", ".to_owned()),
+            (Font::Bold, "--output".to_owned()),
+            (Font::Roman, "=".to_owned()),
+            (Font::Italic, "FILE".to_owned()),
+        ]
+    );
+    assert_eq!(
+        font_spans(r"\f(CBx\f[I]y\f[]z\fSw"),
+        vec![
+            (Font::Bold, "x".to_owned()),
+            (Font::Italic, "y".to_owned()),
+            (Font::Bold, "zw".to_owned()),
+        ]
+    );
 }
 
 // class Deroffer:
-pub struct Deroffer {
+pub struct Deroffer<'a> {
     g_re_word: &'static Regex,
     g_re_number: &'static Regex,
     g_re_not_backslash_or_whitespace: &'static Regex,
@@ -43,15 +217,103 @@ pub struct Deroffer {
     tbl_tab: String,
     eqn: bool,
     output: Cell<String>,
-    skipheaders: bool,
-    skiplists: bool,
+    options: DeroffOptions,
     name: String,
+    font: Font,
+    previous_font: Font,
+    mdoc_xo: Option<String>,
+    link: String,
+    /// Where in the output the `.EX` example we are in starts
+    example_start: Cell<Option<usize>>,
+    /// The parts of the output in examples, which are not filled
+    examples: Cell<Vec<Range<usize>>>,
+    /// The terminator of the `.de` or `.ig` block we are skipping
+    copy_until: Option<String>,
+    aliases: HashMap<String, String>,
+    /// The escape character, `None` after `.eo`
+    escape: Option<char>,
+    /// Whether a `\c` joins the next line onto this one
+    continued: bool,
+    error: Option<String>,
+    string_depth: u8,
+
+    /// The line, borrowed from the input unless it had to be rewritten
+    s: Cow<'a, str>, // This is not explicitly defined in python code
+    /// Where in `s` we are, in bytes
+    pos: usize,
+}
 
-    s: String, // This is not explicitly defined in python code
+/// Switches for what a `Deroffer` leaves in its output, set up like
+/// `DeroffOptions::new().keep_synopsis(true)`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DeroffOptions {
+    skip_headers: bool,
+    keep_synopsis: bool,
+    keep_lists: bool,
+    collapse_blank_lines: bool,
+    font_annotations: bool,
 }
 
-impl Deroffer {
-    pub fn new() -> Deroffer {
+impl Default for DeroffOptions {
+    fn default() -> Self {
+        DeroffOptions {
+            skip_headers: false,
+            keep_synopsis: false,
+            keep_lists: false,
+            collapse_blank_lines: true,
+            font_annotations: false,
+        }
+    }
+}
+
+impl DeroffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave out the titles of sections, subsections and indented paragraphs.
+    pub fn skip_headers(mut self, skip: bool) -> Self {
+        self.skip_headers = skip;
+        self
+    }
+
+    /// Whether the titles are left out, see `skip_headers`.
+    pub fn skips_headers(&self) -> bool {
+        self.skip_headers
+    }
+
+    /// Keep the text of the SYNOPSIS section.
+    pub fn keep_synopsis(mut self, keep: bool) -> Self {
+        self.keep_synopsis = keep;
+        self
+    }
+
+    /// Keep the text of mm lists (`.BL` through `.LE`).
+    pub fn keep_lists(mut self, keep: bool) -> Self {
+        self.keep_lists = keep;
+        self
+    }
+
+    /// Turn runs of blank lines into a single line break.
+    pub fn collapse_blank_lines(mut self, collapse: bool) -> Self {
+        self.collapse_blank_lines = collapse;
+        self
+    }
+
+    /// Keep font changes in the output as `\fR`, `\fB`, `\fI` and `\f(BI`
+    /// annotations, see `font_spans`.
+    pub fn font_annotations(mut self, keep: bool) -> Self {
+        self.font_annotations = keep;
+        self
+    }
+}
+
+impl<'a> Deroffer<'a> {
+    pub fn new() -> Deroffer<'a> {
+        Self::with_options(DeroffOptions::default())
+    }
+
+    pub fn with_options(options: DeroffOptions) -> Deroffer<'a> {
         Deroffer {
             g_re_word: crate::regex!(r##"^[a-zA-Z_]+"##),
             g_re_number: crate::regex!(r##"^[+-]?\d+"##),
@@ -82,302 +344,531 @@ impl Deroffer {
             tbl_tab: String::new(),
             eqn: false,
             output: Cell::new(String::new()),
-            skipheaders: false,
-            skiplists: false,
+            options,
             name: String::new(),
-
-            s: String::new(), // This is not explicitly defined in python code
+            font: Font::Roman,
+            previous_font: Font::Roman,
+            mdoc_xo: None,
+            link: String::new(),
+            example_start: Cell::new(None),
+            examples: Cell::new(Vec::new()),
+            copy_until: None,
+            aliases: HashMap::new(),
+            escape: Some('\\'),
+            continued: false,
+            error: None,
+            string_depth: 0,
+
+            s: Cow::Borrowed(""), // This is not explicitly defined in python code
+            pos: 0,
         }
     }
 
     /// Take the output, leaving the the default value.
     pub fn get_output(&self) -> String {
         let output = self.output.take();
-        match self.g_re_newline_collapse.replace_all(&output, "\n") {
+        let mut examples = self.examples.take();
+        examples.extend(self.example_start.take().map(|start| start..output.len()));
+        if !self.options.collapse_blank_lines {
+            return output;
+        }
+        let collapsed = self
+            .g_re_newline_collapse
+            .replace_all(&output, |caps: &Captures| {
+                // Examples keep their blank lines
+                let m = caps.get(0).unwrap();
+                let kept: usize = examples
+                    .iter()
+                    .map(|example| {
+                        m.end()
+                            .min(example.end)
+                            .saturating_sub(m.start().max(example.start))
+                    })
+                    .sum();
+                "\n".repeat(kept.max(1))
+            });
+        match collapsed {
             Cow::Borrowed(_) => output,
             Cow::Owned(result) => result,
         }
     }
 
-    // for the moment, return small strings, until we figure out what
-    // it should really be doing
+    /// Letters from groff_char(7). A letter produced by a special character
+    /// escape lets `word` carry on, so `caf\(e'` stays one word.
     fn g_specs_specletter(key: &str) -> Option<&'static str> {
         Some(match key {
             // Output composed latin1 letters
-            "-D" => &"Ð",
-            "Sd" => &"ð",
-            "Tp" => &"þ",
-            "TP" => &"Þ",
-            "AE" => &"Æ",
-            "ae" => &"æ",
-            "OE" => &"OE",
-            "oe" => &"oe",
-            ":a" => &"ä",
-            ":A" => &"Ä",
-            ":e" => &"ë",
-            ":E" => &"Ë",
-            ":i" => &"ï",
-            ":I" => &"Ï",
-            ":o" => &"ö",
-            ":O" => &"Ö",
-            ":u" => &"ü",
-            ":U" => &"Ü",
-            ":y" => &"ÿ",
-            "ss" => &"ß",
-            "'A" => &"Á",
-            "'E" => &"É",
-            "'I" => &"Í",
-            "'O" => &"Ó",
-            "'U" => &"Ú",
-            "'Y" => &"Ý",
-            "'a" => &"á",
-            "'e" => &"é",
-            "'i" => &"í",
-            "'o" => &"ó",
-            "'u" => &"ú",
-            "'y" => &"ý",
-            "^A" => &"Â",
-            "^E" => &"Ê",
-            "^I" => &"Î",
-            "^O" => &"Ô",
-            "^U" => &"Û",
-            "^a" => &"â",
-            "^e" => &"ê",
-            "^i" => &"î",
-            "^o" => &"ô",
-            "^u" => &"û",
-            "`A" => &"À",
-            "`E" => &"È",
-            "`I" => &"Ì",
-            "`O" => &"Ò",
-            "`U" => &"Ù",
-            "`a" => &"à",
-            "`e" => &"è",
-            "`i" => &"ì",
-            "`o" => &"ò",
-            "`u" => &"ù",
-            "~A" => &"Ã",
-            "~N" => &"Ñ",
-            "~O" => &"Õ",
-            "~a" => &"ã",
-            "~n" => &"ñ",
-            "~o" => &"õ",
-            ",C" => &"Ç",
-            ",c" => &"ç",
-            "/l" => &"/l",
-            "/L" => &"/L",
-            "/o" => &"ø",
-            "/O" => &"Ø",
-            "oA" => &"Å",
-            "oa" => &"å",
-
-            // Ligatures
-            "fi" => &"fi",
-            "ff" => &"ff",
-            "fl" => &"fl",
-            "Fi" => &"ffi",
-            "Ff" => &"fff",
-            "Fl" => &"ffl",
+            "-D" => "Ð",
+            "Sd" => "ð",
+            "TP" => "Þ",
+            "Tp" => "þ",
+            "ss" => "ß",
+            "AE" => "Æ",
+            "ae" => "æ",
+            "OE" => "Œ",
+            "oe" => "œ",
+            "IJ" => "Ĳ",
+            "ij" => "ĳ",
+            ".i" => "ı",
+            ".j" => "ȷ",
+            ":a" => "ä",
+            ":A" => "Ä",
+            ":e" => "ë",
+            ":E" => "Ë",
+            ":i" => "ï",
+            ":I" => "Ï",
+            ":o" => "ö",
+            ":O" => "Ö",
+            ":u" => "ü",
+            ":U" => "Ü",
+            ":y" => "ÿ",
+            ":Y" => "Ÿ",
+            "'A" => "Á",
+            "'C" => "Ć",
+            "'E" => "É",
+            "'I" => "Í",
+            "'O" => "Ó",
+            "'U" => "Ú",
+            "'Y" => "Ý",
+            "'a" => "á",
+            "'c" => "ć",
+            "'e" => "é",
+            "'i" => "í",
+            "'o" => "ó",
+            "'u" => "ú",
+            "'y" => "ý",
+            "^A" => "Â",
+            "^E" => "Ê",
+            "^I" => "Î",
+            "^O" => "Ô",
+            "^U" => "Û",
+            "^a" => "â",
+            "^e" => "ê",
+            "^i" => "î",
+            "^o" => "ô",
+            "^u" => "û",
+            "`A" => "À",
+            "`E" => "È",
+            "`I" => "Ì",
+            "`O" => "Ò",
+            "`U" => "Ù",
+            "`a" => "à",
+            "`e" => "è",
+            "`i" => "ì",
+            "`o" => "ò",
+            "`u" => "ù",
+            "~A" => "Ã",
+            "~N" => "Ñ",
+            "~O" => "Õ",
+            "~a" => "ã",
+            "~n" => "ñ",
+            "~o" => "õ",
+            "vS" => "Š",
+            "vs" => "š",
+            "vZ" => "Ž",
+            "vz" => "ž",
+            ",C" => "Ç",
+            ",c" => "ç",
+            "/l" => "ł",
+            "/L" => "Ł",
+            "/o" => "ø",
+            "/O" => "Ø",
+            "oA" => "Å",
+            "oa" => "å",
+
+            // Ligatures are spelled out, since nobody searches for "ﬁle"
+            "fi" => "fi",
+            "ff" => "ff",
+            "fl" => "fl",
+            "Fi" => "ffi",
+            "Ff" => "fff",
+            "Fl" => "ffl",
+
+            // Greek
+            "*A" => "Α",
+            "*B" => "Β",
+            "*G" => "Γ",
+            "*D" => "Δ",
+            "*E" => "Ε",
+            "*Z" => "Ζ",
+            "*Y" => "Η",
+            "*H" => "Θ",
+            "*I" => "Ι",
+            "*K" => "Κ",
+            "*L" => "Λ",
+            "*M" => "Μ",
+            "*N" => "Ν",
+            "*C" => "Ξ",
+            "*O" => "Ο",
+            "*P" => "Π",
+            "*R" => "Ρ",
+            "*S" => "Σ",
+            "*T" => "Τ",
+            "*U" => "Υ",
+            "*F" => "Φ",
+            "*X" => "Χ",
+            "*Q" => "Ψ",
+            "*W" => "Ω",
+            "*a" => "α",
+            "*b" => "β",
+            "*g" => "γ",
+            "*d" => "δ",
+            "*e" => "ε",
+            "*z" => "ζ",
+            "*y" => "η",
+            "*h" => "θ",
+            "*i" => "ι",
+            "*k" => "κ",
+            "*l" => "λ",
+            "*m" => "μ",
+            "*n" => "ν",
+            "*c" => "ξ",
+            "*o" => "ο",
+            "*p" => "π",
+            "*r" => "ρ",
+            "ts" => "ς",
+            "*s" => "σ",
+            "*t" => "τ",
+            "*u" => "υ",
+            "*f" => "φ",
+            "*x" => "χ",
+            "*q" => "ψ",
+            "*w" => "ω",
+            "+h" => "ϑ",
+            "+f" => "ϕ",
+            "+p" => "ϖ",
+            "+e" => "ϵ",
             _ => return None,
         })
     }
 
-    // Much like the above, return small strings for now until we know what
-    // we might actually want to do
+    /// Every other named glyph from groff_char(7), mapped to what `-Tutf8`
+    /// would print. Dashes stay ASCII so option names survive.
     fn g_specs(key: &str) -> Option<&'static str> {
         Some(match key {
-            "mi" => &"-",
-            "en" => &"-",
-            "hy" => &"-",
-            "em" => &"--",
-            "lq" => &"“",
-            "rq" => &"”",
-            "Bq" => &",,",
-            "oq" => &"`",
-            "cq" => &"'",
-            "aq" => &"'",
-            "dq" => &"\"",
-            "or" => &"|",
-            "at" => &"@",
-            "sh" => &"#",
-            // For the moment, faithfully mimic the behavior of the Python script,
-            // even though it might seem that &"€" is a more appropriate result here
-            "Eu" => &"¤",
-            "eu" => &"¤",
-            "Do" => &"$",
-            "ct" => &"¢",
-            "Fo" => &"«",
-            "Fc" => &"»",
-            "fo" => &"<",
-            "fc" => &">",
-            "r!" => &"¡",
-            "r?" => &"¿",
-            "Of" => &"ª",
-            "Om" => &"º",
-            "pc" => &"·",
-            "S1" => &"¹",
-            "S2" => &"²",
-            "S3" => &"³",
-            "<-" => &"<-",
-            "->" => &"->",
-            "<>" => &"<->",
-            "ua" => &"^",
-            "da" => &"v",
-            "lA" => &"<=",
-            "rA" => &"=>",
-            "hA" => &"<=>",
-            "uA" => &"^^",
-            "dA" => &"vv",
-            "ba" => &"|",
-            "bb" => &"|",
-            "br" => &"|",
-            "bv" => &"|",
-            "ru" => &"_",
-            "ul" => &"_",
-            "ci" => &"O",
-            "bu" => &"o",
-            "co" => &"©",
-            "rg" => &"®",
-            "tm" => &"(TM)",
-            "dd" => &"||",
-            "dg" => &"|",
-            "ps" => &"¶",
-            "sc" => &"§",
-            "de" => &"°",
-            "%0" => &"0/00",
-            "14" => &"¼",
-            "12" => &"½",
-            "34" => &"¾",
-            "f/" => &"/",
-            "sl" => &"/",
-            "rs" => &"\\",
-            "sq" => &"[]",
-            "fm" => &"'",
-            "ha" => &"^",
-            "ti" => &"~",
-            "lB" => &"[",
-            "rB" => &"]",
-            "lC" => &"{",
-            "rC" => &"}",
-            "la" => &"<",
-            "ra" => &">",
-            "lh" => &"<=",
-            "rh" => &"=>",
-            "tf" => &"therefore",
-            "~~" => &"~~",
-            "~=" => &"~=",
-            "!=" => &"!=",
-            "**" => &"*",
-            "+-" => &"±",
-            "<=" => &"<=",
-            "==" => &"==",
-            "=~" => &"=~",
-            ">=" => &">=",
-            "AN" => &"\\/",
-            "OR" => &"/\\",
-            "no" => &"¬",
-            "te" => &"there exists",
-            "fa" => &"for all",
-            "Ah" => &"aleph",
-            "Im" => &"imaginary",
-            "Re" => &"real",
-            "if" => &"infinity",
-            "md" => &"·",
-            "mo" => &"member of",
-            "mu" => &"×",
-            "nm" => &"not member of",
-            "pl" => &"+",
-            "eq" => &"=",
-            "pt" => &"oc",
-            "pp" => &"perpendicular",
-            "sb" => &"(=",
-            "sp" => &"=)",
-            "ib" => &"(-",
-            "ip" => &"-)",
-            "ap" => &"~",
-            "is" => &"I",
-            "sr" => &"root",
-            "pd" => &"d",
-            "c*" => &"(x)",
-            "c+" => &"(+)",
-            "ca" => &"cap",
-            "cu" => &"U",
-            "di" => &"÷",
-            "gr" => &"V",
-            "es" => &"{}",
-            "CR" => &"_|",
-            "st" => &"such that",
-            "/_" => &"/_",
-            "lz" => &"<>",
-            "an" => &"-",
-
-            // Output Greek
-            "*A" => &"Alpha",
-            "*B" => &"Beta",
-            "*C" => &"Xi",
-            "*D" => &"Delta",
-            "*E" => &"Epsilon",
-            "*F" => &"Phi",
-            "*G" => &"Gamma",
-            "*H" => &"Theta",
-            "*I" => &"Iota",
-            "*K" => &"Kappa",
-            "*L" => &"Lambda",
-            "*M" => &"Mu",
-            "*N" => &"Nu",
-            "*O" => &"Omicron",
-            "*P" => &"Pi",
-            "*Q" => &"Psi",
-            "*R" => &"Rho",
-            "*S" => &"Sigma",
-            "*T" => &"Tau",
-            "*U" => &"Upsilon",
-            "*W" => &"Omega",
-            "*X" => &"Chi",
-            "*Y" => &"Eta",
-            "*Z" => &"Zeta",
-            "*a" => &"alpha",
-            "*b" => &"beta",
-            "*c" => &"xi",
-            "*d" => &"delta",
-            "*e" => &"epsilon",
-            "*f" => &"phi",
-            "+f" => &"phi",
-            "*g" => &"gamma",
-            "*h" => &"theta",
-            "+h" => &"theta",
-            "*i" => &"iota",
-            "*k" => &"kappa",
-            "*l" => &"lambda",
-            "*m" => &"µ",
-            "*n" => &"nu",
-            "*o" => &"omicron",
-            "*p" => &"pi",
-            "+p" => &"omega",
-            "*q" => &"psi",
-            "*r" => &"rho",
-            "*s" => &"sigma",
-            "*t" => &"tau",
-            "*u" => &"upsilon",
-            "*w" => &"omega",
-            "*x" => &"chi",
-            "*y" => &"eta",
-            "*z" => &"zeta",
-            "ts" => &"sigma",
+            // Dashes and hyphens
+            "mi" => "-",
+            "hy" => "-",
+            "en" => "-",
+            "em" => "-",
+
+            // Quotes
+            "Bq" => "„",
+            "bq" => "‚",
+            "lq" => "“",
+            "rq" => "”",
+            "oq" => "‘",
+            "cq" => "’",
+            "aq" => "'",
+            "dq" => "\"",
+            "Fo" => "«",
+            "Fc" => "»",
+            "fo" => "‹",
+            "fc" => "›",
+
+            // Punctuation
+            "r!" => "¡",
+            "r?" => "¿",
+            "at" => "@",
+            "sh" => "#",
+            "rs" => "\\",
+            "sl" => "/",
+            "or" => "|",
+            "ba" => "|",
+            "br" => "│",
+            "bb" => "¦",
+            "ul" => "_",
+            "ru" => "_",
+            "rn" => "‾",
+            "ci" => "○",
+            "bu" => "•",
+            "dd" => "‡",
+            "dg" => "†",
+            "lz" => "◊",
+            "sq" => "□",
+            "ps" => "¶",
+            "sc" => "§",
+            "lh" => "☜",
+            "rh" => "☞",
+            "CR" => "↵",
+            "OK" => "✓",
+
+            // Brackets
+            "lB" => "[",
+            "rB" => "]",
+            "lC" => "{",
+            "rC" => "}",
+            "la" => "⟨",
+            "ra" => "⟩",
+            "bv" => "⎪",
+            "braceex" => "⎪",
+            "bracketlefttp" => "⎡",
+            "bracketleftex" => "⎢",
+            "bracketleftbt" => "⎣",
+            "bracketrighttp" => "⎤",
+            "bracketrightex" => "⎥",
+            "bracketrightbt" => "⎦",
+            "lt" | "bracelefttp" => "⎧",
+            "lk" | "braceleftmid" => "⎨",
+            "lb" | "braceleftbt" => "⎩",
+            "braceleftex" => "⎪",
+            "rt" | "bracerighttp" => "⎫",
+            "rk" | "bracerightmid" => "⎬",
+            "rb" | "bracerightbt" => "⎭",
+            "bracerightex" => "⎪",
+            "parenlefttp" => "⎛",
+            "parenleftex" => "⎜",
+            "parenleftbt" => "⎝",
+            "parenrighttp" => "⎞",
+            "parenrightex" => "⎟",
+            "parenrightbt" => "⎠",
+
+            // Arrows
+            "<-" => "←",
+            "->" => "→",
+            "<>" => "↔",
+            "da" => "↓",
+            "ua" => "↑",
+            "va" => "↕",
+            "lA" => "⇐",
+            "rA" => "⇒",
+            "hA" => "⇔",
+            "dA" => "⇓",
+            "uA" => "⇑",
+            "vA" => "⇕",
+            "an" => "⎯",
+
+            // Accents
+            "a\"" => "˝",
+            "a-" => "¯",
+            "a." => "˙",
+            "a^" => "^",
+            "aa" => "´",
+            "ga" => "`",
+            "ab" => "˘",
+            "ac" => "¸",
+            "ad" => "¨",
+            "ah" => "ˇ",
+            "ao" => "˚",
+            "a~" => "~",
+            "ho" => "˛",
+            "ha" => "^",
+            "ti" => "~",
+
+            // Legal symbols and currency
+            "co" => "©",
+            "rg" => "®",
+            "tm" => "™",
+            "Do" => "$",
+            "ct" => "¢",
+            "eu" => "€",
+            "Eu" => "€",
+            "Ye" => "¥",
+            "Po" => "£",
+            "Cs" => "¤",
+            "Fn" => "ƒ",
+
+            // Units
+            "de" => "°",
+            "%0" => "‰",
+            "fm" => "′",
+            "sd" => "″",
+            "mc" => "µ",
+            "Of" => "ª",
+            "Om" => "º",
+
+            // Logical symbols
+            "AN" => "∧",
+            "OR" => "∨",
+            "no" | "tno" => "¬",
+            "te" => "∃",
+            "fa" => "∀",
+            "st" => "∋",
+            "3d" | "tf" => "∴",
+
+            // Mathematical symbols
+            "12" => "½",
+            "14" => "¼",
+            "34" => "¾",
+            "18" => "⅛",
+            "38" => "⅜",
+            "58" => "⅝",
+            "78" => "⅞",
+            "S1" => "¹",
+            "S2" => "²",
+            "S3" => "³",
+            "pl" => "+",
+            "-+" => "∓",
+            "+-" | "t+-" => "±",
+            "pc" => "·",
+            "md" => "⋅",
+            "mu" | "tmu" => "×",
+            "c*" => "⊗",
+            "c+" => "⊕",
+            "di" | "tdi" => "÷",
+            "f/" => "⁄",
+            "**" => "∗",
+            "<=" => "≤",
+            ">=" => "≥",
+            "<<" => "≪",
+            ">>" => "≫",
+            "eq" => "=",
+            "!=" => "≠",
+            "==" => "≡",
+            "ne" => "≢",
+            "=~" => "≅",
+            "|=" => "≃",
+            "ap" => "∼",
+            "~~" | "~=" => "≈",
+            "pt" => "∝",
+            "es" => "∅",
+            "mo" => "∈",
+            "nm" => "∉",
+            "sb" => "⊂",
+            "nb" => "⊄",
+            "sp" => "⊃",
+            "nc" => "⊅",
+            "ib" => "⊆",
+            "ip" => "⊇",
+            "ca" => "∩",
+            "cu" => "∪",
+            "/_" => "∠",
+            "pp" => "⊥",
+            "is" | "integral" => "∫",
+            "sum" => "∑",
+            "product" => "∏",
+            "coproduct" => "∐",
+            "gr" => "∇",
+            "sr" | "sqrt" => "√",
+            "lc" => "⌈",
+            "rc" => "⌉",
+            "lf" => "⌊",
+            "rf" => "⌋",
+            "if" => "∞",
+            "Ah" => "ℵ",
+            "Im" => "ℑ",
+            "Re" => "ℜ",
+            "wp" => "℘",
+            "pd" => "∂",
+            "-h" | "hbar" => "ℏ",
+
+            // Card symbols
+            "CL" => "♣",
+            "SP" => "♠",
+            "HE" => "♥",
+            "DI" => "♦",
             _ => return None,
         })
     }
 
+    /// Combining characters for the accents allowed in composite glyphs,
+    /// as in `\[e aa]`.
+    fn g_specs_composite(key: &str) -> Option<char> {
+        Some(match key {
+            "a\"" => '\u{30B}',
+            "a-" => '\u{304}',
+            "a." => '\u{307}',
+            "a^" | "ha" | "^" => '\u{302}',
+            "aa" | "'" => '\u{301}',
+            "ga" | "`" => '\u{300}',
+            "ab" => '\u{306}',
+            "ac" => '\u{327}',
+            "ad" | ":" => '\u{308}',
+            "ah" => '\u{30C}',
+            "ao" => '\u{30A}',
+            "a~" | "ti" | "~" => '\u{303}',
+            "ho" => '\u{328}',
+            _ => return None,
+        })
+    }
+
+    /// Resolve the name of a special character, as found in `\(xx`,
+    /// `\[name]` and `\C'name'`, along with whether it is a letter.
+    fn glyph(name: &str) -> Option<(String, bool)> {
+        if let Some(value) = Self::g_specs_specletter(name) {
+            return Some((value.to_owned(), true));
+        }
+        if let Some(value) = Self::g_specs(name) {
+            return Some((value.to_owned(), false));
+        }
+
+        let value = Self::unicode_glyph(name).or_else(|| {
+            // Composite glyph: a base glyph followed by accents
+            if !name.contains(' ') {
+                return None;
+            }
+            let mut parts = name.split_whitespace();
+            let base = parts.next()?;
+            let mut value = match Self::glyph(base) {
+                Some((value, _)) => value,
+                None if char_len(base) == 1 => base.to_owned(),
+                None => return None,
+            };
+            for accent in parts {
+                value.push(Self::g_specs_composite(accent)?);
+            }
+            Some(value)
+        })?;
+        let letter = value.chars().all(char::is_alphabetic);
+        Some((value, letter))
+    }
+
+    /// `u` followed by one or more `_`-separated hex code points, or `char`
+    /// followed by a decimal one.
+    fn unicode_glyph(name: &str) -> Option<String> {
+        if let Some(code) = name.strip_prefix("char") {
+            return code.parse().ok().and_then(char::from_u32).map(String::from);
+        }
+
+        name.strip_prefix('u')?
+            .split('_')
+            .map(|code| {
+                if (4..=6).contains(&code.len()) && code.chars().all(|c| c.is_ascii_hexdigit()) {
+                    u32::from_str_radix(code, 16).ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The rest of the line, from the cursor on.
+    fn rest(&self) -> &str {
+        &self.s[self.pos..]
+    }
+
+    /// Start over on `line`.
+    fn set_line(&mut self, line: impl Into<Cow<'a, str>>) {
+        self.s = line.into();
+        self.pos = 0;
+    }
+
+    /// Skip `amount` characters of the line.
     fn skip_char(&mut self, amount: usize) {
-        self.s.drain(0..amount);
+        let rest = self.rest();
+        self.pos += rest
+            .char_indices()
+            .nth(amount)
+            .map_or(rest.len(), |(i, _)| i);
+    }
+
+    /// Skip `amount` bytes of the line, for offsets from `find` and regex
+    /// matches, which are always on a character boundary.
+    fn skip_bytes(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+
+    fn skip_rest(&mut self) {
+        self.pos = self.s.len();
     }
 
     fn skip_leading_whitespace(&mut self) {
-        self.s = self.s.trim_start().to_owned();
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
     }
 
     fn str_at(&self, idx: usize) -> &str {
-        let s = &self.s;
+        let s = self.rest();
         s.char_indices()
             .skip(idx)
             .next()
@@ -412,16 +903,16 @@ impl Deroffer {
     fn text_arg(&mut self) -> bool {
         let mut got_something = false;
         loop {
-            let possible = self.g_re_not_backslash_or_whitespace.find(&self.s);
+            let possible = self.g_re_not_backslash_or_whitespace.find(self.rest());
             if let Some(m) = possible {
                 // Output the characters in the match
                 self.condputs(m.as_str());
                 let end = m.end();
-                self.skip_char(end);
+                self.skip_bytes(end);
                 got_something = true;
             }
 
-            if self.s.is_empty() || self.is_white(0) {
+            if self.rest().is_empty() || self.is_white(0) {
                 return got_something;
             }
 
@@ -450,7 +941,22 @@ impl Deroffer {
             "RB" => self.macro_i_ir(),
             "RI" => self.macro_i_ir(),
             "AB" => self.macro_i_ir(),
+            "SY" => self.macro_sy(),
+            "YS" => self.macro_sp(),
+            "OP" => self.macro_op(),
+            "TQ" => self.macro_tq(),
+            "UR" => self.macro_ur_mt(),
+            "MT" => self.macro_ur_mt(),
+            "UE" => self.macro_ue_me(),
+            "ME" => self.macro_ue_me(),
+            "EX" => self.macro_ex(),
+            "EE" => self.macro_ee(),
             "Nm" => self.macro_nm(),
+            "Sh" => self.macro_sh(),
+            "Ss" => self.macro_ss_ip(),
+            "Bl" | "El" | "Bd" | "Ed" | "Pp" => self.macro_lp_pp(),
+            "It" | "Nd" => self.macro_mdoc(),
+            s if is_mdoc_macro(s) => self.macro_mdoc(),
             "] " => self.macro_close_bracket(),
             "PS" => self.macro_ps(),
             "PE" => self.macro_pe(),
@@ -461,7 +967,6 @@ impl Deroffer {
             "EN" => self.macro_en(),
             "R1" => self.macro_r1(),
             "R2" => self.macro_r2(),
-            "de" => self.macro_de(),
             "BL" => self.macro_bl_vl(),
             "VL" => self.macro_bl_vl(),
             "AL" => self.macro_bl_vl(),
@@ -483,10 +988,26 @@ impl Deroffer {
         }
     }
 
+    /// The groff requests, which unlike the macros above are matched by their
+    /// whole name, so that `.defcolor` isn't `.de`.
+    fn request_dispatch(&mut self, name: &str) -> Option<bool> {
+        Some(match name {
+            "de" | "de1" | "dei" | "dei1" | "am" | "am1" | "ami" | "ami1" => self.macro_de_am(),
+            "ig" => self.macro_ig(),
+            "rm" => self.macro_rm(),
+            "rn" => self.macro_rn(),
+            "als" => self.macro_als(),
+            "as" => self.macro_as(),
+            "ec" => self.macro_ec(),
+            "eo" => self.macro_eo(),
+            _ => return None,
+        })
+    }
+
     fn macro_sh(&mut self) -> bool {
         let headers = [" SYNOPSIS", " \"SYNOPSIS", " ‹BERSICHT", " \"‹BERSICHT"];
-        // @TODO: In the future s[2..] should care about UTF-8
-        if headers.iter().any(|header| self.s[2..].starts_with(header)) {
+        let title = self.rest().get(2..).unwrap_or_default();
+        if !self.options.keep_synopsis && headers.iter().any(|header| title.starts_with(header)) {
             self.inheader = true;
         } else {
             self.inheader = false;
@@ -501,19 +1022,255 @@ impl Deroffer {
     }
 
     fn macro_i_ir(&mut self) -> bool {
-        false
+        if !self.options.font_annotations {
+            return false;
+        }
+
+        // `.B` and `.I` set all their arguments in one font, `.BR` and
+        // friends alternate between two fonts
+        let mut names = self.rest().chars().take(2).map(|c| c.to_string());
+        let first = names.next().as_deref().and_then(Font::from_name);
+        let second = match names.next().as_deref() {
+            Some(" ") => first,
+            name => name.and_then(Font::from_name),
+        };
+        let (first, second) = match (first, second) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return false,
+        };
+        let alternating = self.str_at(1) != " ";
+
+        let font = self.font;
+        let args = Self::macro_args(self.rest().get(2..).unwrap_or_default());
+        for (idx, arg) in args.into_iter().enumerate() {
+            if idx % 2 == 0 {
+                self.switch_font(first);
+            } else {
+                self.switch_font(second);
+            }
+            if idx > 0 && !alternating {
+                self.condputs(" ");
+            }
+            self.set_line(arg);
+            self.text();
+        }
+        self.switch_font(font);
+        self.condputs("\n");
+        self.skip_rest();
+        true
+    }
+
+    /// Split the arguments of a macro, which may be double quoted. Escapes are
+    /// left for the caller to interpret.
+    pub fn macro_args(line: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut chars = line.trim_end_matches('\n').chars().peekable();
+        loop {
+            while matches!(chars.peek(), Some(' ' | '\t')) {
+                chars.next();
+            }
+            let mut arg = String::new();
+            match chars.peek() {
+                None => return args,
+                Some('"') => {
+                    chars.next();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            // `""` is a literal quote inside a quoted argument
+                            '"' if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                arg.push('"');
+                            }
+                            '"' => break,
+                            _ => arg.push(c),
+                        }
+                    }
+                }
+                Some(_) => {
+                    while let Some(&c) = chars.peek() {
+                        if c == ' ' || c == '\t' {
+                            break;
+                        }
+                        arg.push(c);
+                        chars.next();
+                        // An escaped space does not end the argument
+                        if c == '\\' {
+                            arg.extend(chars.next());
+                        }
+                    }
+                }
+            }
+            args.push(arg);
+        }
     }
 
     fn macro_nm(&mut self) -> bool {
-        if self.s == "Nm\n" {
+        if self.rest() == "Nm\n" {
             self.condputs(&self.name);
+        } else if !self.name.is_empty() {
+            // Once the name is known, `.Nm ,` and such are just text
+            return self.macro_mdoc();
         } else {
-            self.name = self.s.get(3..).unwrap_or_default().trim().into();
+            self.name = self.rest().get(3..).unwrap_or_default().trim().into();
             self.name.push(' ');
         }
         true
     }
 
+    /// Render a line of mdoc macros, such as `.It Fl o Ar file`, to its text.
+    /// Macros called from the arguments are rendered along the way.
+    fn macro_mdoc(&mut self) -> bool {
+        let mut args = Self::macro_args(self.rest()).into_iter().peekable();
+        let mut line = MdocLine::default();
+        // Between `Xo` and `Xc` the lines make up a single item header
+        if let Some(header) = &mut self.mdoc_xo {
+            line.text = std::mem::take(header);
+        }
+        match args.peek().map(String::as_str) {
+            Some("It") => {
+                args.next();
+                self.condputs("\n");
+            }
+            Some("Nd") => {
+                args.next();
+                line.word(&format!("{}\\-", self.name));
+            }
+            _ => {}
+        }
+        while let Some(arg) = args.next() {
+            self.mdoc_macro(&arg, &mut args, &mut line);
+        }
+
+        if let Some(header) = &mut self.mdoc_xo {
+            *header = line.text;
+            return true;
+        }
+        self.set_line(line.text);
+        self.text();
+        self.condputs("\n");
+        true
+    }
+
+    fn mdoc_macro(&mut self, name: &str, args: &mut MdocArgs, line: &mut MdocLine) {
+        let (open, close) = match name {
+            "Fl" => {
+                let mut flags = 0;
+                while let Some(flag) = args.next_if(|arg| !is_mdoc_macro(arg) && !is_punct(arg)) {
+                    line.word(&format!("\\fB-{}\\fR", flag));
+                    flags += 1;
+                }
+                if flags == 0 {
+                    // `Fl Fl output` is `--output`
+                    line.word("\\fB-\\fR");
+                    line.nospace = true;
+                }
+                return;
+            }
+            "Ar" => {
+                if !Self::mdoc_words(args, line, "\\fI") {
+                    line.word("\\fIfile ...\\fR");
+                }
+                return;
+            }
+            "Nm" => {
+                if !Self::mdoc_words(args, line, "\\fB") && !self.name.is_empty() {
+                    line.word(&format!("\\fB{}\\fR", self.name.trim()));
+                }
+                return;
+            }
+            "Cm" | "Ic" | "Sy" => {
+                Self::mdoc_words(args, line, "\\fB");
+                return;
+            }
+            "Em" | "Pa" | "Va" => {
+                Self::mdoc_words(args, line, "\\fI");
+                return;
+            }
+            "Ad" | "Dv" | "Er" | "Ev" | "Li" | "No" | "Sx" => {
+                Self::mdoc_words(args, line, "");
+                return;
+            }
+            "Xr" => {
+                if let Some(page) = args.next_if(|arg| !is_mdoc_macro(arg)) {
+                    match args.next_if(|arg| !is_mdoc_macro(arg) && !is_punct(arg)) {
+                        Some(section) => line.word(&format!("{}({})", page, section)),
+                        None => line.word(&page),
+                    }
+                }
+                return;
+            }
+            "Ns" => {
+                line.nospace = true;
+                return;
+            }
+            "Ta" => {
+                line.text.push('\t');
+                line.nospace = true;
+                return;
+            }
+            "Oo" => {
+                line.word("[");
+                return;
+            }
+            "Oc" => {
+                line.word("]");
+                return;
+            }
+            "Xo" => {
+                self.mdoc_xo = Some(String::new());
+                return;
+            }
+            "Xc" => {
+                self.mdoc_xo = None;
+                return;
+            }
+            "Op" | "Bq" => ("[", "]"),
+            "Pq" => ("(", ")"),
+            "Aq" => ("<", ">"),
+            "Dq" | "Qq" => ("\"", "\""),
+            "Sq" | "Ql" => ("'", "'"),
+            word => {
+                line.word(word);
+                return;
+            }
+        };
+
+        // Enclosing macros take the rest of the line, except for trailing
+        // punctuation
+        let mut inner: Vec<String> = args.collect();
+        let trailing = inner.iter().rev().take_while(|arg| is_punct(arg)).count();
+        let trailing = inner.split_off(inner.len() - trailing);
+        let mut inner = inner.into_iter().peekable();
+        if inner.next_if_eq("Ns").is_some() {
+            line.nospace = true;
+        }
+        line.word(open);
+        line.nospace = true;
+        while let Some(arg) = inner.next() {
+            self.mdoc_macro(&arg, &mut inner, line);
+        }
+        line.nospace = true;
+        line.word(close);
+        for punct in trailing {
+            line.word(&punct);
+        }
+    }
+
+    /// Add the arguments up to the next macro or punctuation in the given font,
+    /// returning whether there were any.
+    fn mdoc_words(args: &mut MdocArgs, line: &mut MdocLine, font: &str) -> bool {
+        let mut any = false;
+        while let Some(word) = args.next_if(|arg| !is_mdoc_macro(arg) && !is_punct(arg)) {
+            if font.is_empty() {
+                line.word(&word);
+            } else {
+                line.word(&format!("{}{}\\fR", font, word));
+            }
+            any = true;
+        }
+        any
+    }
+
     fn macro_close_bracket(&mut self) -> bool {
         self.refer = false;
         false
@@ -535,10 +1292,92 @@ impl Deroffer {
         true
     }
 
+    /// `.EX` starts an example, which is kept as it is, without filling.
+    fn macro_ex(&mut self) -> bool {
+        self.condputs("\n");
+        if self.example_start.get().is_none() {
+            self.example_start.set(Some(self.output_len()));
+        }
+        true
+    }
+
+    /// `.EE` ends an example.
+    fn macro_ee(&mut self) -> bool {
+        if let Some(start) = self.example_start.take() {
+            let mut examples = self.examples.take();
+            examples.push(start..self.output_len());
+            self.examples.set(examples);
+        }
+        self.condputs("\n");
+        true
+    }
+
+    fn output_len(&self) -> usize {
+        let output = self.output.take();
+        let len = output.len();
+        self.output.set(output);
+        len
+    }
+
+    /// `.SY command` starts a synopsis with the command in bold, see
+    /// groff_man(7).
+    fn macro_sy(&mut self) -> bool {
+        let command = Self::macro_args(self.rest().get(2..).unwrap_or_default()).join(" ");
+        self.condputs("\n");
+        self.set_line(format!("\\fB{}\\fR\n", command));
+        self.text()
+    }
+
+    /// `.OP -x arg` is an optional option in a synopsis, set as `[-x arg]`.
+    fn macro_op(&mut self) -> bool {
+        let mut args = Self::macro_args(self.rest().get(2..).unwrap_or_default()).into_iter();
+        let mut line = String::from("[");
+        if let Some(option) = args.next() {
+            line.push_str(&format!("\\fB{}\\fR", option));
+        }
+        for arg in args {
+            line.push_str(&format!(" \\fI{}\\fR", arg));
+        }
+        line.push_str("]\n");
+        self.set_line(line);
+        self.text()
+    }
+
+    /// `.TQ` adds another tag to the one of the `.TP` before, so we put them
+    /// on one line: `-v, --verbose`.
+    fn macro_tq(&mut self) -> bool {
+        if self.take_back_newline() {
+            self.condputs(", ");
+        } else {
+            self.condputs("\n");
+        }
+        true
+    }
+
+    /// `.UR url` and `.MT address` start a link, which `.UE` and `.ME` end. The
+    /// lines between are the link text.
+    fn macro_ur_mt(&mut self) -> bool {
+        self.link = Self::macro_args(self.rest().get(2..).unwrap_or_default()).join(" ");
+        true
+    }
+
+    /// End a link with its target, `text <url>`, followed by the arguments
+    /// such as punctuation.
+    fn macro_ue_me(&mut self) -> bool {
+        let link = std::mem::take(&mut self.link);
+        if self.take_back_newline() {
+            self.condputs(" ");
+        }
+        let trailing = Self::macro_args(self.rest().get(2..).unwrap_or_default()).join(" ");
+        self.set_line(format!("<{}>{}\n", link, trailing));
+        self.text()
+    }
+
     fn macro_ts(&mut self) -> bool {
         if self.is_white(2) {
             self.tbl = true;
             self.tblstate = TblState::Options;
+            self.tbl_tab.clear();
         }
 
         self.condputs("\n");
@@ -601,14 +1440,98 @@ impl Deroffer {
         true
     }
 
-    fn macro_de(&mut self) -> bool {
-        self.r#macro = 1;
+    /// The arguments of the request on the line, after its name.
+    fn request_args(&self) -> Vec<String> {
+        let rest = self.rest();
+        let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let args = &rest[name_end..];
+        // A `\"` comment ends the arguments
+        let args = args.find("\\\"").map_or(args, |i| &args[..i]);
+        Self::macro_args(args)
+    }
+
+    /// `.de name [end]` and `.am name [end]` define and append to a macro,
+    /// whose body we skip up to `..` or `.end`.
+    fn macro_de_am(&mut self) -> bool {
+        let end = self.request_args().get(1).cloned();
+        self.copy_until = Some(end.unwrap_or_else(|| ".".to_owned()));
         self.condputs("\n");
         true
     }
 
+    /// `.ig [end]` ignores the lines up to `..` or `.end`.
+    fn macro_ig(&mut self) -> bool {
+        let end = self.request_args().into_iter().next();
+        self.copy_until = Some(end.unwrap_or_else(|| ".".to_owned()));
+        true
+    }
+
+    /// `.rm name ...` removes strings and macros.
+    fn macro_rm(&mut self) -> bool {
+        for name in self.request_args() {
+            self.reg_table.remove(&name);
+            self.aliases.remove(&name);
+        }
+        true
+    }
+
+    /// `.rn old new` renames a string or macro.
+    fn macro_rn(&mut self) -> bool {
+        if let [old, new, ..] = &self.request_args()[..] {
+            if let Some(value) = self.reg_table.remove(old) {
+                self.reg_table.insert(new.clone(), value);
+            } else {
+                let target = self.aliases.remove(old).unwrap_or_else(|| old.clone());
+                self.aliases.insert(new.clone(), target);
+            }
+        }
+        true
+    }
+
+    /// `.als new old` makes `new` another name for the string or macro `old`.
+    fn macro_als(&mut self) -> bool {
+        if let [new, old, ..] = &self.request_args()[..] {
+            if let Some(value) = self.reg_table.get(old).cloned() {
+                self.reg_table.insert(new.clone(), value);
+            } else {
+                let target = self.aliases.get(old).unwrap_or(old).clone();
+                self.aliases.insert(new.clone(), target);
+            }
+        }
+        true
+    }
+
+    /// `.as name value` appends to a string.
+    fn macro_as(&mut self) -> bool {
+        self.skip_char(2);
+        self.skip_leading_whitespace();
+        if let Some((name, value)) = self.rest().split_once(' ') {
+            // A leading quote keeps leading spaces in the value
+            let value = value.trim_end();
+            let value = value.strip_prefix('"').unwrap_or(value).to_owned();
+            self.reg_table
+                .entry(name.to_owned())
+                .or_default()
+                .push_str(&value);
+        }
+        true
+    }
+
+    /// `.ec [c]` makes `c` the escape character, a backslash by default.
+    fn macro_ec(&mut self) -> bool {
+        let arg = self.request_args().into_iter().next();
+        self.escape = Some(arg.and_then(|arg| arg.chars().next()).unwrap_or('\\'));
+        true
+    }
+
+    /// `.eo` turns escapes off, until the next `.ec`.
+    fn macro_eo(&mut self) -> bool {
+        self.escape = None;
+        true
+    }
+
     fn macro_bl_vl(&mut self) -> bool {
-        if self.is_white(2) {
+        if self.is_white(2) && !self.options.keep_lists {
             self.inlist = true
         }
         self.condputs("\n");
@@ -650,7 +1573,7 @@ impl Deroffer {
         self.skip_leading_whitespace();
 
         if !self.str_at(0).is_empty() {
-            let comps: Vec<String> = self.s.splitn(2, " ").map(|s| s.into()).collect();
+            let comps: Vec<String> = self.rest().splitn(2, " ").map(|s| s.into()).collect();
 
             if comps.len() == 2 {
                 let name: String = comps.get(0).unwrap().into();
@@ -674,24 +1597,26 @@ impl Deroffer {
         self.skip_char(2);
         self.skip_leading_whitespace();
 
-        while !self.s.is_empty() && &self.s[0..=0] != "\n" {
-            self.tr_from.push_str(&self.s[0..=0]);
-
-            let ns = &self.s[1..=1];
-            self.tr_to
-                .push_str(if ns.is_empty() || ns == "\n" { " " } else { ns });
-
-            self.skip_char(2);
+        // Pairs of characters, the first translated to the second
+        let (mut from, mut to) = (String::new(), String::new());
+        let mut chars = self.rest().chars().take_while(|&c| c != '\n');
+        while let Some(c) = chars.next() {
+            from.push(c);
+            to.push(chars.next().unwrap_or(' '));
         }
+        self.tr_from.push_str(&from);
+        self.tr_to.push_str(&to);
 
         // Update our table, then swap in the slower tr-savvy condputs
-        self.tr = match TranslationTable::new(&self.tr_from, &self.tr_to) {
-            Ok(table) => Some(table),
-            Err(e) => panic!(
-                "Encountered an error creating a new translation table from {}, {}: {}",
-                self.tr_from, self.tr_to, e
-            ),
-        };
+        match TranslationTable::new(&self.tr_from, &self.tr_to) {
+            Ok(table) => self.tr = Some(table),
+            Err(e) => {
+                self.error = Some(format!(
+                    "Encountered an error creating a new translation table from {}, {}: {}",
+                    self.tr_from, self.tr_to, e
+                ))
+            }
+        }
         true
     }
 
@@ -706,10 +1631,10 @@ impl Deroffer {
     }
 
     fn request_or_macro(&mut self) -> bool {
-        // self.s[0] is a period or open single quote
+        // self.rest()[0] is a period or open single quote
         self.skip_char(1);
 
-        match self.s.chars().nth(1) {
+        match self.rest().chars().nth(1) {
             Some('[') => {
                 self.refer = true;
                 self.condputs("\n");
@@ -729,24 +1654,38 @@ impl Deroffer {
         }
 
         self.nobody = false;
-        let s0s1 = self.s.chars().take(2).collect::<String>();
+        if !self.aliases.is_empty() {
+            let rest = self.rest();
+            let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if let Some(target) = self.aliases.get(&rest[..name_end]) {
+                let line = format!("{}{}", target, &rest[name_end..]);
+                self.set_line(line);
+            }
+        }
+        let rest = self.rest();
+        let name = rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())].to_owned();
+        if let Some(handled) = self.request_dispatch(&name) {
+            return handled;
+        }
+
+        let s0s1 = self.rest().chars().take(2).collect::<String>();
 
         if self.g_macro_dispatch(&s0s1) {
             return true;
         }
 
-        if SKIP_HEADERS && self.nobody {
+        if self.options.skip_headers && self.nobody {
             return true;
         }
 
         self.skip_leading_whitespace();
-        while !self.s.is_empty() && !self.is_white(0) {
+        while !self.rest().is_empty() && !self.is_white(0) {
             self.skip_char(1);
         }
         self.skip_leading_whitespace();
         loop {
             if !self.quoted_arg() && !self.text_arg() {
-                if !self.s.is_empty() {
+                if !self.rest().is_empty() {
                     self.condputs(self.str_at(0));
                     self.skip_char(1);
                 } else {
@@ -757,15 +1696,34 @@ impl Deroffer {
     }
 
     fn font(&mut self) -> bool {
-        if let Some(m) = self.g_re_font.find(&self.s) {
+        if let Some(m) = self.g_re_font.find(self.rest()) {
             let end = m.end();
-            self.skip_char(end);
+            if self.options.font_annotations {
+                let name = m.as_str()[2..].trim_start_matches(&['(', '['][..]);
+                match name.trim_end_matches(']') {
+                    "P" | "" => self.switch_font(self.previous_font),
+                    name => {
+                        if let Some(font) = Font::from_name(name) {
+                            self.switch_font(font);
+                        }
+                    }
+                }
+            }
+            self.skip_bytes(end);
             true
         } else {
             false
         }
     }
 
+    fn switch_font(&mut self, font: Font) {
+        if font != self.font {
+            self.previous_font = self.font;
+            self.font = font;
+            self.condputs(font.escape());
+        }
+    }
+
     fn numreq(&mut self) -> bool {
         if !"hvwud".contains(self.str_at(1)) || self.str_at(2) != "'" {
             return false;
@@ -796,10 +1754,10 @@ impl Deroffer {
     // NOTE: there is a call to text_arg that is commented out because it's not implemented, so the
     // tests will need revised when it gets implemented
     fn var(&mut self) -> bool {
-        let s0s1 = self.s.chars().take(2).collect::<String>();
+        let s0s1 = self.rest().chars().take(2).collect::<String>();
 
         if s0s1 == "\\n" {
-            if "dy" == self.s.chars().skip(3).take(2).collect::<String>()
+            if "dy" == self.rest().chars().skip(3).take(2).collect::<String>()
                 || (self.str_at(2) == "(" && self.not_whitespace(3) && self.not_whitespace(4))
             {
                 self.skip_char(5);
@@ -819,7 +1777,7 @@ impl Deroffer {
         } else if s0s1 == "\\*" {
             let mut reg = String::new();
             if self.str_at(2) == "(" && self.not_whitespace(3) && self.not_whitespace(4) {
-                reg = self.s[3..5].to_owned();
+                reg = self.rest().chars().skip(3).take(2).collect();
                 self.skip_char(5);
             } else if self.str_at(2) == "[" && self.not_whitespace(3) {
                 self.skip_char(3);
@@ -827,7 +1785,7 @@ impl Deroffer {
                     reg.push_str(self.str_at(0));
                     self.skip_char(1);
                 }
-                if let Some(']') = self.s.chars().next() {
+                if let Some(']') = self.rest().chars().next() {
                     self.skip_char(1);
                 } else {
                     return false;
@@ -840,9 +1798,18 @@ impl Deroffer {
             }
 
             if self.reg_table.contains_key(&reg) {
+                // A string that refers to itself would expand forever
+                if self.string_depth >= MAX_STRING_DEPTH {
+                    self.error = Some(format!("string `{}` expands into itself", reg));
+                    self.skip_rest();
+                    return true;
+                }
                 // This unwrap is safe because of the if
-                self.s = self.reg_table.get(&reg).unwrap().to_owned();
+                let value = self.reg_table.get(&reg).unwrap().to_owned();
+                self.set_line(value);
+                self.string_depth += 1;
                 self.text_arg();
+                self.string_depth -= 1;
                 true
             } else {
                 false
@@ -867,12 +1834,13 @@ impl Deroffer {
 
     fn esc(&mut self) -> bool {
         // We require that the string start with backslash
-        if let Some(c) = self.s.chars().nth(1) {
+        if let Some(c) = self.rest().chars().nth(1) {
             match c {
                 'e' | 'E' => self.condputs("\\"),
                 't' => self.condputs("\t"),
                 '0' | '~' => self.condputs(" "),
-                '|' | '^' | '&' | ':' => (),
+                // Spacing, italic corrections and the like print nothing
+                '|' | '^' | '&' | ':' | ',' | '/' => (),
                 _ => self.condputs(c.to_string()),
             };
             self.skip_char(2);
@@ -882,13 +1850,85 @@ impl Deroffer {
         }
     }
 
+    /// `\c` ends the line without a break, the next line carries on from it.
+    /// Whatever follows on this line is dropped.
+    fn interrupt(&mut self) -> bool {
+        self.skip_char(2);
+        self.comment();
+        self.continued = true;
+        true
+    }
+
+    /// `\zc` prints `c` without moving on, to be overstruck by what follows,
+    /// so we leave `c` out.
+    fn zero_width(&mut self) -> bool {
+        self.skip_char(2);
+        self.r#macro += 1;
+        if !(self.rest().starts_with('\\') && self.esc_char_backslash()) {
+            self.skip_char(1);
+        }
+        self.r#macro -= 1;
+        true
+    }
+
+    /// `\o'abc'` overstrikes its characters, we keep the first one.
+    fn overstrike(&mut self) -> bool {
+        match Self::delimited(&self.rest()[2..]) {
+            Some((arg, len)) => {
+                if let Some(c) = arg.chars().next().filter(|&c| c != '\\') {
+                    self.condputs(c.to_string());
+                }
+                self.skip_bytes(2 + len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Skip escapes like `\X'...'` and `\R'...'`, which draw, set registers
+    /// or talk to the device and print nothing we want.
+    fn skip_delimited(&mut self) -> bool {
+        match Self::delimited(&self.rest()[2..]) {
+            Some((_, len)) => {
+                self.skip_bytes(2 + len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Skip escapes taking a name, `\Yx`, `\Y(xx` or `\Y[xxx]`.
+    fn skip_name(&mut self) -> bool {
+        let rest = &self.rest()[2..];
+        let len = match rest.chars().next() {
+            Some('(') => rest.chars().take(3).map(char::len_utf8).sum(),
+            Some('[') => match rest.find(']') {
+                Some(end) => end + 1,
+                None => return false,
+            },
+            Some(c) if c != '\n' => c.len_utf8(),
+            _ => return false,
+        };
+        self.skip_bytes(2 + len);
+        true
+    }
+
+    /// The argument of an escape like `\X'...'`, between a pair of delimiters,
+    /// and how many bytes it takes up along with them.
+    fn delimited(s: &str) -> Option<(&str, usize)> {
+        let delimiter = s.chars().next().filter(|c| !c.is_whitespace())?;
+        let start = delimiter.len_utf8();
+        let len = s[start..].find(delimiter)?;
+        Some((&s[start..start + len], start + len + delimiter.len_utf8()))
+    }
+
     fn word(&mut self) -> bool {
         let mut got_something = false;
-        while let Some(m) = self.g_re_word.find(&self.s) {
+        while let Some(m) = self.g_re_word.find(self.rest()) {
             got_something = true;
             self.condputs(m.as_str());
             let end = m.end();
-            self.skip_char(end);
+            self.skip_bytes(end);
 
             while self.spec() {
                 if !self.specletter {
@@ -901,16 +1941,16 @@ impl Deroffer {
 
     fn text(&mut self) -> bool {
         loop {
-            if let Some(idx) = self.s.find("\\") {
-                self.condputs(&self.s[..idx]);
-                self.skip_char(idx);
+            if let Some(idx) = self.rest().find("\\") {
+                self.condputs(&self.rest()[..idx]);
+                self.skip_bytes(idx);
                 if !self.esc_char_backslash() {
                     self.condputs(self.str_at(0));
                     self.skip_char(1);
                 }
             } else {
-                self.condputs(&self.s);
-                self.s = String::new();
+                self.condputs(self.rest());
+                self.skip_rest();
                 return true;
             }
         }
@@ -919,19 +1959,38 @@ impl Deroffer {
     fn spec(&mut self) -> bool {
         self.specletter = false;
 
-        if self.s.get(..2) == Some("\\(") && self.not_whitespace(2) && self.not_whitespace(3) {
-            let key = self.s.get(2..4).unwrap_or_default();
-
-            if let Some(value) = Self::g_specs_specletter(key) {
-                self.condputs(value);
-                self.specletter = true;
-            } else if let Some(value) = Self::g_specs(key) {
-                self.condputs(value);
-            }
-
+        if self.rest().get(..2) == Some("\\(") && self.not_whitespace(2) && self.not_whitespace(3) {
+            let key: String = self.rest().chars().skip(2).take(2).collect();
+            self.put_glyph(&key);
             self.skip_char(4);
             true
-        } else if self.s.starts_with("\\%") {
+        } else if self.rest().starts_with("\\[") {
+            // \[name], \[u00E9] or \[char233]
+            match self.rest().find(']') {
+                Some(end) => {
+                    let name = self.rest()[2..end].to_owned();
+                    self.put_glyph(&name);
+                    self.skip_bytes(end + 1);
+                    true
+                }
+                None => false,
+            }
+        } else if self.rest().starts_with("\\C") || self.rest().starts_with("\\N") {
+            // \C'name' or \N'233'
+            match Self::delimited(&self.rest()[2..]) {
+                Some((name, len)) => {
+                    let name = if self.rest().starts_with("\\C") {
+                        name.to_owned()
+                    } else {
+                        format!("char{}", name)
+                    };
+                    self.put_glyph(&name);
+                    self.skip_bytes(2 + len);
+                    true
+                }
+                None => false,
+            }
+        } else if self.rest().starts_with("\\%") {
             self.specletter = true;
             self.skip_char(2);
             true
@@ -940,15 +1999,30 @@ impl Deroffer {
         }
     }
 
+    /// Output the named glyph, if we know it.
+    fn put_glyph(&mut self, name: &str) {
+        if let Some((value, letter)) = Self::glyph(name) {
+            self.condputs(value);
+            self.specletter = letter;
+        }
+    }
+
     fn esc_char_backslash(&mut self) -> bool {
-        if let Some(c) = self.s.chars().nth(1) {
+        if let Some(c) = self.rest().chars().nth(1) {
             match c {
                 '"' => self.comment(),
                 'f' => self.font(),
                 's' => self.size(),
                 'h' | 'v' | 'w' | 'u' | 'd' => self.numreq(),
                 'n' | '*' => self.var(),
-                '(' => self.spec(),
+                '(' | '[' | 'C' | 'N' => self.spec(),
+                'c' => self.interrupt(),
+                'z' => self.zero_width(),
+                'o' => self.overstrike(),
+                'A' | 'b' | 'B' | 'D' | 'H' | 'l' | 'L' | 'R' | 'S' | 'x' | 'X' | 'Z' => {
+                    self.skip_delimited()
+                }
+                'k' | 'm' | 'M' | 'Y' => self.skip_name(),
                 _ => self.esc(),
             }
         } else {
@@ -959,26 +2033,40 @@ impl Deroffer {
     /// AKA `prch`
     fn not_whitespace(&self, idx: usize) -> bool {
         // # Note that this return False for the empty string (idx >= len(self.s))
-        // ch = self.s[idx:idx+1]
+        // ch = self.rest()[idx:idx+1]
         // return ch not in ' \t\n'
         // TODO Investigate checking for ASCII whitespace after mvp
-        self.s
+        self.rest()
             .chars()
             .nth(idx)
             .map(|op| !op.is_whitespace())
             .unwrap_or_default()
     }
+
+    /// Whether output is held back, in a picture, an equation, a reference,
+    /// a macro definition, a list or the synopsis.
+    fn is_special(&self) -> bool {
+        self.pic || self.eqn || self.refer || self.r#macro != 0 || self.inlist || self.inheader
+    }
+
+    /// Take back the newline ending the output, to carry on the line.
+    fn take_back_newline(&self) -> bool {
+        if self.is_special() {
+            return false;
+        }
+        let mut o = self.output.take();
+        let popped = o.ends_with('\n') && o.pop().is_some();
+        self.output.set(o);
+        popped
+    }
+
     /// `condputs` (cond)itionally (puts) `s` into `self.output`
     /// if `self.tr` is set, instead of putting `s` into `self.output` directly,
     /// it `translate`s it using the set translation table and puts the result
     /// into `self.output`
     fn condputs<S: AsRef<str>>(&self, s: S) {
         let s = s.as_ref();
-        let is_special = {
-            self.pic || self.eqn || self.refer || self.r#macro != 0 || self.inlist || self.inheader
-        };
-
-        if !is_special {
+        if !self.is_special() {
             let mut o = self.output.take();
             if let Some(table) = &self.tr {
                 o.push_str(&table.translate(s));
@@ -995,10 +2083,10 @@ impl Deroffer {
     }
 
     fn number(&mut self) -> bool {
-        if let Some(mat) = self.g_re_number.find(&self.s) {
+        if let Some(mat) = self.g_re_number.find(self.rest()) {
             self.condputs(mat.as_str());
             let end = mat.end();
-            self.skip_char(end);
+            self.skip_bytes(end);
             true
         } else {
             false
@@ -1006,7 +2094,7 @@ impl Deroffer {
     }
 
     fn esc_char(&mut self) -> bool {
-        if self.s.chars().next() == Some('\\') {
+        if self.rest().chars().next() == Some('\\') {
             self.esc_char_backslash()
         } else {
             self.word() || self.number()
@@ -1019,7 +2107,7 @@ impl Deroffer {
             // surrounded by double quotes. (We've found the first one—really
             // hoping we find its mate later).
             self.skip_char(1);
-            while !self.s.is_empty() && self.str_at(0) != "\"" {
+            while !self.rest().is_empty() && self.str_at(0) != "\"" {
                 if !self.esc_char() {
                     self.condputs(self.str_at(0));
                     self.skip_char(1);
@@ -1037,8 +2125,13 @@ impl Deroffer {
 
     fn do_tbl(&mut self) -> bool {
         match self.tblstate {
+            // The options line is optional
+            TblState::Options if !self.rest().contains(';') => {
+                self.tblstate = TblState::Format;
+                return self.do_tbl();
+            }
             TblState::Options => {
-                while !self.s.is_empty() && !"\n;".contains(self.str_at(0)) {
+                while !self.rest().is_empty() && !"\n;".contains(self.str_at(0)) {
                     self.skip_leading_whitespace();
 
                     if !self.str_at(0).chars().all(|c| c.is_alphabetic()) {
@@ -1047,21 +2140,22 @@ impl Deroffer {
                         // Parse option
 
                         // find first non-alphabetic character
-                        match self.s.char_indices().find(|(_, c)| !c.is_alphabetic()) {
+                        match self.rest().char_indices().find(|(_, c)| !c.is_alphabetic()) {
                             Some((idx, '(')) => {
                                 // self.s -> option '(' arg ')' rest
-                                let option = &self.s[..idx];
-                                let mut iter = self.s[idx + 1..].splitn(2, ')');
+                                let line = &self.s[self.pos..];
+                                let option = &line[..idx];
+                                let mut iter = line[idx + 1..].splitn(2, ')');
                                 let arg = iter.next().unwrap_or_default();
                                 let rest = iter.next().unwrap_or_default();
 
                                 if option.to_lowercase() == "tab" {
-                                    self.tbl_tab = arg.get(0..1).unwrap_or_default().to_owned();
+                                    self.tbl_tab = arg.chars().take(1).collect();
                                 }
 
-                                self.s = rest.to_owned();
+                                self.pos = self.s.len() - rest.len();
                             }
-                            _ => self.s.clear(),
+                            _ => self.skip_rest(),
                         }
                     }
                 }
@@ -1069,7 +2163,7 @@ impl Deroffer {
                 self.condputs("\n");
             }
             TblState::Format => {
-                while !self.s.is_empty() && !".\n".contains(self.str_at(0)) {
+                while !self.rest().is_empty() && !".\n".contains(self.str_at(0)) {
                     self.skip_leading_whitespace();
                     if !self.str_at(0).is_empty() {
                         self.skip_char(1);
@@ -1083,24 +2177,104 @@ impl Deroffer {
             }
             TblState::Data => {
                 if !self.tbl_tab.is_empty() {
-                    self.s = self.s.replace(&self.tbl_tab, "\t");
+                    let line = self.rest().replace(&self.tbl_tab, "\t");
+                    self.set_line(line);
+                }
+
+                // A row consisting of only `_` or `=` is a horizontal rule
+                if let "_" | "=" = self.rest().trim_end() {
+                    self.skip_rest();
+                } else {
+                    self.tbl_cells();
+                }
+            }
+            TblState::TextBlock { empty } => {
+                if self.rest().starts_with("T}") {
+                    // The rest of the line carries on with the row
+                    self.skip_char(2);
+                    self.tblstate = TblState::Data;
+                    return self.do_tbl();
+                }
+
+                // Lines in a text block, requests included, are filled into one cell
+                let mut output = self.output.take();
+                let len = output.len();
+                self.output.set(output);
+
+                if self.rest().starts_with('.') || self.rest().starts_with('\'') {
+                    self.request_or_macro();
+                } else {
+                    self.text();
                 }
 
-                self.text();
+                output = self.output.take();
+                let text = output.split_off(len);
+                let text = text.trim_matches('\n');
+                if !text.is_empty() {
+                    if !empty {
+                        output.push(' ');
+                    }
+                    output.push_str(text);
+                }
+                self.output.set(output);
+                self.tblstate = TblState::TextBlock {
+                    empty: empty && text.is_empty(),
+                };
             }
         }
 
         true
     }
 
+    /// Output a row of data as tab separated cells. A cell that opens a text
+    /// block (`T{`) ends the line; the row resumes after the closing `T}`.
+    fn tbl_cells(&mut self) {
+        let line = self.rest().to_owned();
+        self.skip_rest();
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line.as_str(), ""),
+        };
+
+        for (idx, cell) in line.split('\t').enumerate() {
+            if idx > 0 {
+                self.condputs("\t");
+            }
+            match cell {
+                "T{" => {
+                    self.tblstate = TblState::TextBlock { empty: true };
+                    return;
+                }
+                // Vertical spans and rules have no text of their own
+                "\\^" | "_" | "=" | "\\_" | "\\=" => {}
+                _ => {
+                    self.set_line(cell.to_owned());
+                    self.text();
+                }
+            }
+        }
+        self.condputs(newline);
+    }
+
+    fn in_tbl_text_block(&self) -> bool {
+        matches!(self.tblstate, TblState::TextBlock { .. })
+    }
+
     fn do_line(&mut self) -> bool {
-        match self
-            .s
-            .bytes()
-            .next()
-            .expect("`do_line` called when `self.s` was empty")
-        {
-            b'.' | b'\'' => self.request_or_macro(),
+        if let Some(end) = &self.copy_until {
+            // The block ends at a line with just `..`, or the end macro given
+            let rest = self.rest();
+            if rest.starts_with(['.', '\''].as_ref()) && rest[1..].trim() == end {
+                self.copy_until = None;
+            }
+            return true;
+        }
+
+        match self.rest().bytes().next() {
+            None => true,
+            Some(b'.' | b'\'') if !(self.tbl && self.in_tbl_text_block()) => {
+                self.request_or_macro()
+            }
             _ => {
                 if self.tbl {
                     self.do_tbl()
@@ -1111,64 +2285,106 @@ impl Deroffer {
         }
     }
 
-    pub fn deroff(&mut self, s: String) {
-        let lines = s.split('\n');
-        for line in lines {
-            self.s = line.to_owned() + "\n";
-            if !self.do_line() {
+    /// Deroff `s`, adding the text to the output. Input the deroffer cannot
+    /// get past stops it with an error for the line in question.
+    pub fn deroff(&mut self, s: &'a str) -> Result<(), DeroffError> {
+        // Lines are borrowed as they are, and only copied to join them or swap
+        // in backslashes for another escape character
+        let mut joined = String::new();
+        let mut start = 0;
+        for (idx, line) in s.split('\n').enumerate() {
+            let end = start + line.len();
+            let with_newline = s.get(start..=end);
+            start = end + 1;
+            let line = match (self.escape, with_newline) {
+                (Some('\\'), Some(with_newline))
+                    if joined.is_empty() && !ends_with_escape(line) =>
+                {
+                    Cow::Borrowed(with_newline)
+                }
+                (escape, _) => {
+                    match escape {
+                        Some('\\') => joined.push_str(line),
+                        // Swap in backslashes for the escape character
+                        escape => {
+                            for c in line.chars() {
+                                match c {
+                                    '\\' => joined.push_str("\\e"),
+                                    c if Some(c) == escape => joined.push('\\'),
+                                    c => joined.push(c),
+                                }
+                            }
+                        }
+                    }
+                    // An escape ending the line joins the next one onto it
+                    if ends_with_escape(&joined) {
+                        joined.pop();
+                        continue;
+                    }
+                    joined.push('\n');
+                    Cow::Owned(std::mem::take(&mut joined))
+                }
+            };
+            self.set_line(line);
+            if self.continued && self.copy_until.is_none() {
+                self.continued = false;
+                self.take_back_newline();
+            }
+            let more = self.do_line();
+            if let Some(message) = self.error.take() {
+                return Err(DeroffError {
+                    line: idx + 1,
+                    message,
+                });
+            }
+            if !more {
                 break;
             }
         }
+        Ok(())
     }
 }
 
+/// Whether an odd number of backslashes ends `line`, the last escaping the
+/// newline.
+fn ends_with_escape(line: &str) -> bool {
+    line.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
 #[test]
 fn test_comment() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "\n".to_owned();
+    deroffer.set_line("\n");
     deroffer.comment();
-    assert_eq!(deroffer.s, "\n".to_owned());
+    assert_eq!(deroffer.rest(), "\n");
 
-    deroffer.s = "hello\n".to_owned();
+    deroffer.set_line("hello\n");
     deroffer.comment();
-    assert_eq!(deroffer.s, "\n".to_owned());
+    assert_eq!(deroffer.rest(), "\n");
 
-    deroffer.s = "hello\nworld".to_owned();
+    deroffer.set_line("hello\nworld");
     deroffer.comment();
-    assert_eq!(deroffer.s, "\nworld".to_owned());
+    assert_eq!(deroffer.rest(), "\nworld");
 }
 
 fn deroff_files(files: &[String]) -> io::Result<()> {
     for arg in files {
         eprintln!("{}", arg);
         let mut file = File::open(arg)?;
-        let mut string = String::new();
+        let mut bytes = Vec::new();
         if arg.ends_with(".gz") {
-            let mut decoder = GzDecoder::new(file);
-            decoder.read_to_string(&mut string)?;
+            GzDecoder::new(file).read_to_end(&mut bytes)?;
         } else {
-            match file.read_to_string(&mut string) {
-                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
-                    // not valid UTF-8
-                    // TODO: This is a _bad_ workaround for latin1, we need to correctly decode input files
-                    let mut bytes = Vec::new();
-                    file.read_to_end(&mut bytes)?;
-
-                    for byte in bytes {
-                        string.push(byte as char);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("unknown error {:?}", e);
-                    continue;
-                }
-                Ok(_) => {}
-            };
+            file.read_to_end(&mut bytes)?;
         }
+        let string = crate::lossy_unicode(bytes);
 
         let mut deroffer = Deroffer::new();
-        deroffer.deroff(string);
+        if let Err(e) = deroffer.deroff(&string) {
+            eprintln!("{}: {}", arg, e);
+            continue;
+        }
         deroffer.flush_output(io::stdout());
     }
 
@@ -1178,83 +2394,136 @@ fn deroff_files(files: &[String]) -> io::Result<()> {
 #[test]
 fn test_text_arg() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.text_arg());
-    assert_eq!(deroffer.s, " World!");
+    assert_eq!(deroffer.rest(), " World!");
     assert_eq!(deroffer.output.take(), "Hello");
 
     let mut deroffer = Deroffer::new();
     assert!(!deroffer.text_arg());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert!(deroffer.output.take().is_empty());
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "\t\n\t           \t\n".into();
+    deroffer.set_line("\t\n\t           \t\n");
     assert!(!deroffer.text_arg());
-    assert_eq!(deroffer.s, "\t\n\t           \t\n");
+    assert_eq!(deroffer.rest(), "\t\n\t           \t\n");
     assert!(deroffer.output.take().is_empty());
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = r"\r".into();
+    deroffer.set_line(r"\r");
     assert!(!deroffer.text_arg());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert_eq!(deroffer.output.take(), "r");
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "Applebees".into();
+    deroffer.set_line("Applebees");
     assert!(deroffer.text_arg());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Applebees");
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "忍一时风平浪静，退一步海阔天空。".into();
+    deroffer.set_line("忍一时风平浪静，退一步海阔天空。");
     assert!(deroffer.text_arg());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "忍一时风平浪静，退一步海阔天空。");
 }
 
 #[test]
 fn test_font() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = r"\f(aa)lemon".into();
+    deroffer.set_line(r"\f(aa)lemon");
     assert!(deroffer.font());
-    assert_eq!(deroffer.s, ")lemon");
+    assert_eq!(deroffer.rest(), ")lemon");
     assert!(!deroffer.font());
-    assert_eq!(deroffer.s, ")lemon");
+    assert_eq!(deroffer.rest(), ")lemon");
+}
+
+#[test]
+fn test_font_annotations() {
+    let mut deroffer = Deroffer::with_options(DeroffOptions::new().font_annotations(true));
+    deroffer.set_line(r"\fB\-o\fR, \f(CB\-\-output\fP=\f[I]FILE\f[]");
+    assert!(deroffer.text());
+    assert_eq!(
+        deroffer.output.take(),
+        r"\fB-o\fR, \fB--output\fR=\fIFILE\fR"
+    );
+
+    // Unknown fonts are dropped
+    deroffer.set_line(r"\f(aa)lemon");
+    assert!(deroffer.font());
+    assert_eq!(deroffer.rest(), ")lemon");
+    assert!(deroffer.output.take().is_empty());
+}
+
+#[test]
+fn test_macro_i_ir() {
+    let mut deroffer = Deroffer::new();
+    deroffer.set_line("BR \\-x foo\n");
+    assert!(!deroffer.macro_i_ir());
+
+    let mut deroffer = Deroffer::with_options(DeroffOptions::new().font_annotations(true));
+    deroffer.set_line("BR \\-x foo\n");
+    assert!(deroffer.macro_i_ir());
+    assert_eq!(deroffer.output.take(), "\\fB-x\\fRfoo\n");
+
+    deroffer.set_line("B \\-\\-all \"of them\"\n");
+    assert!(deroffer.macro_i_ir());
+    assert_eq!(deroffer.output.take(), "\\fB--all of them\\fR\n");
+
+    deroffer.set_line("IR file \\fB.fish\\fR suffix\n");
+    assert!(deroffer.macro_i_ir());
+    assert_eq!(
+        deroffer.output.take(),
+        "\\fIfile\\fR\\fB.fish\\fR\\fIsuffix\\fR\n"
+    );
+
+    // `.AB` shares the dispatch but is not a font macro
+    deroffer.set_line("AB\n");
+    assert!(!deroffer.macro_i_ir());
+}
+
+#[test]
+fn test_macro_args() {
+    assert!(Deroffer::macro_args("").is_empty());
+    assert_eq!(
+        Deroffer::macro_args(" \\-f  \"a \"\"b\"\"\" c\\ d\n"),
+        vec!["\\-f", "a \"b\"", "c\\ d"]
+    );
 }
 
 #[test]
 fn test_numreq() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(!deroffer.numreq());
-    assert_eq!(deroffer.s, "Hello World!");
+    assert_eq!(deroffer.rest(), "Hello World!");
     assert!(deroffer.output.take().is_empty());
 
-    deroffer.s = r"\w'Apple'".into();
+    deroffer.set_line(r"\w'Apple'");
     assert!(deroffer.numreq());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert!(deroffer.output.take().is_empty());
 
-    deroffer.s = r"\w'Hello\tWorld!'".into();
+    deroffer.set_line(r"\w'Hello\tWorld!'");
     assert!(deroffer.numreq());
-    assert_eq!(deroffer.s, "!'");
+    assert_eq!(deroffer.rest(), "!'");
     assert!(deroffer.output.take().is_empty());
 }
 
 #[test]
 fn test_size() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(!deroffer.size());
 
-    deroffer.s = r"\s10Hello World!".into();
+    deroffer.set_line(r"\s10Hello World!");
     assert!(deroffer.size());
-    assert_eq!(deroffer.s, "Hello World!");
+    assert_eq!(deroffer.rest(), "Hello World!");
 
-    deroffer.s = r"\s-11 ignore me".into();
+    deroffer.set_line(r"\s-11 ignore me");
     assert!(deroffer.size());
-    assert_eq!(deroffer.s, " ignore me");
+    assert_eq!(deroffer.rest(), " ignore me");
 
     assert!(deroffer.output.take().is_empty());
 }
@@ -1265,48 +2534,48 @@ fn test_esc() {
     assert!(!deroffer.esc());
 
     // This is UB, but it's the same UB as the python
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.esc());
     assert_eq!(deroffer.output.take(), "\\");
 
-    deroffer.s = r"\E".into();
+    deroffer.set_line(r"\E");
     assert!(deroffer.esc());
     assert_eq!(deroffer.output.take(), "\\");
-    deroffer.s = r"\t".into();
+    deroffer.set_line(r"\t");
     assert!(deroffer.esc());
     assert_eq!(deroffer.output.take(), "\t");
 
-    deroffer.s = r"\~".into();
+    deroffer.set_line(r"\~");
     assert!(deroffer.esc());
     assert_eq!(deroffer.output.take(), " ");
 
-    deroffer.s = r"\|".into();
+    deroffer.set_line(r"\|");
     assert!(deroffer.esc());
     assert!(deroffer.output.take().is_empty());
 
-    deroffer.s = r"\apple".into();
+    deroffer.set_line(r"\apple");
     assert!(deroffer.esc());
     assert_eq!(deroffer.output.take(), "a");
-    assert_eq!(deroffer.s, "pple");
+    assert_eq!(deroffer.rest(), "pple");
 }
 
 #[test]
 fn test_word() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.word());
-    assert_eq!(deroffer.s, " World!");
+    assert_eq!(deroffer.rest(), " World!");
     assert_eq!(deroffer.output.take(), "Hello");
 
-    deroffer.s = "Hello\\(ps".into();
+    deroffer.set_line("Hello\\(ps");
     assert!(deroffer.word());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello¶");
 
-    deroffer.s = "100 thousand".into();
+    deroffer.set_line("100 thousand");
     assert!(!deroffer.word());
-    assert_eq!(deroffer.s, "100 thousand");
+    assert_eq!(deroffer.rest(), "100 thousand");
     assert_eq!(deroffer.output.take(), "");
 }
 
@@ -1314,29 +2583,29 @@ fn test_word() {
 fn test_text() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello World!");
 
-    deroffer.s = "Hello\tWorld!".into();
+    deroffer.set_line("Hello\tWorld!");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello\tWorld!");
 
-    deroffer.s = "Hello\\(psWorld!".into();
+    deroffer.set_line("Hello\\(psWorld!");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello¶World!");
 
-    deroffer.s = "Hello 10 World!".into();
+    deroffer.set_line("Hello 10 World!");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello 10 World!");
 
-    deroffer.s = "你好世界！".into();
+    deroffer.set_line("你好世界！");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "你好世界！");
 }
 
@@ -1344,50 +2613,50 @@ fn test_text() {
 fn test_esc_char_backslash() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = r#"\"This is a comment, it will be ignored"#.into();
+    deroffer.set_line(r#"\"This is a comment, it will be ignored"#);
     assert!(deroffer.esc_char_backslash());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
 
     // This gets passed to `font`, so i stole a test from there
-    deroffer.s = r"\f(aa)lemon".into();
+    deroffer.set_line(r"\f(aa)lemon");
     assert!(deroffer.esc_char_backslash());
-    assert_eq!(deroffer.s, ")lemon");
+    assert_eq!(deroffer.rest(), ")lemon");
 
     // This gets passed to `size`
-    deroffer.s = r"\s-11 ignore me".into();
+    deroffer.set_line(r"\s-11 ignore me");
     assert!(deroffer.esc_char_backslash());
-    assert_eq!(deroffer.s, " ignore me");
+    assert_eq!(deroffer.rest(), " ignore me");
 
     // You get the idea
     // Taken from numreq
-    deroffer.s = r"\w'Apple'".into();
+    deroffer.set_line(r"\w'Apple'");
     assert!(deroffer.esc_char_backslash());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert!(deroffer.output.take().is_empty());
 
     // Taken from var
-    deroffer.s = "\\*[test_reg]".to_owned();
+    deroffer.set_line("\\*[test_reg]");
     deroffer
         .reg_table
         .insert("test_reg".to_owned(), "It me!".to_owned());
     assert!(deroffer.esc_char_backslash());
-    assert_eq!(deroffer.s, " me!");
+    assert_eq!(deroffer.rest(), " me!");
     assert!(deroffer.output.take().contains("It"));
 
     // Taken from spec
-    deroffer.s = "\\(Sdaaaa".into(); // `ð`
+    deroffer.set_line("\\(Sdaaaa"); // `ð`
     assert!(deroffer.esc_char_backslash());
     assert!(deroffer.specletter);
     assert_eq!(deroffer.output.take(), "ð");
-    assert_eq!(deroffer.s, "aaaa");
+    assert_eq!(deroffer.rest(), "aaaa");
 
     // Taken from esc
-    deroffer.s = r"\E".into();
+    deroffer.set_line(r"\E");
     assert!(deroffer.esc_char_backslash());
     assert_eq!(deroffer.output.take(), "\\");
 
     // This is UB, but it's the same UB as the python
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.esc_char_backslash());
     assert_eq!(deroffer.output.take(), "\\");
 }
@@ -1396,18 +2665,18 @@ fn test_esc_char_backslash() {
 fn test_esc_char() {
     // Gets passed to esc_char_backslash, stealing one test to make sure it works
     let mut deroffer = Deroffer::new();
-    deroffer.s = r#"\"This is a comment, it will be ignored"#.into();
+    deroffer.set_line(r#"\"This is a comment, it will be ignored"#);
     assert!(deroffer.esc_char());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
 
     // Will get passed to word, stealing a test
-    deroffer.s = "Hello\\(ps".into();
+    deroffer.set_line("Hello\\(ps");
     assert!(deroffer.esc_char());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello¶");
 
     // Will get passed to number, stealing a test
-    deroffer.s = String::from("4343xx7");
+    deroffer.set_line("4343xx7");
     assert_eq!(deroffer.number(), true);
     assert_eq!(deroffer.output.take(), "4343".to_string());
 }
@@ -1415,14 +2684,14 @@ fn test_esc_char() {
 #[test]
 fn test_quoted_arg() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = r#""Hello World!""#.into();
+    deroffer.set_line(r#""Hello World!""#);
     assert!(deroffer.quoted_arg());
-    assert_eq!(deroffer.s, "\"");
+    assert_eq!(deroffer.rest(), "\"");
     assert_eq!(deroffer.output.take(), "Hello World!");
 
-    deroffer.s = r#""Hello\(psWorld""#.into();
+    deroffer.set_line(r#""Hello\(psWorld""#);
     assert!(deroffer.quoted_arg());
-    assert_eq!(deroffer.s, "\"");
+    assert_eq!(deroffer.rest(), "\"");
     assert_eq!(deroffer.output.take(), "Hello¶World");
 }
 
@@ -1431,23 +2700,23 @@ fn test_do_line() {
     let mut deroffer = Deroffer::new();
 
     // Gets passed to request_or_macro, stealing a test
-    deroffer.s = ".SH".into();
+    deroffer.set_line(".SH");
     assert!(deroffer.do_line());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert!(deroffer.output.take().is_empty());
 
     // same, to_tbl
-    deroffer.s = "aaa(bbb);Hello World!".into();
+    deroffer.set_line("aaa(bbb);Hello World!");
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert_eq!(deroffer.s, ";Hello World!");
+    assert_eq!(deroffer.rest(), ";Hello World!");
     assert_eq!(deroffer.output.take(), "\n");
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     // same, text
-    deroffer.s = "Hello\\(psWorld!".into();
+    deroffer.set_line("Hello\\(psWorld!");
     assert!(deroffer.text());
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello¶World!");
 }
 
@@ -1455,32 +2724,32 @@ fn test_do_line() {
 fn test_request_or_macro() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "'_[Hello".into();
+    deroffer.set_line("'_[Hello");
     assert!(deroffer.request_or_macro());
     assert!(deroffer.refer);
-    assert_eq!(deroffer.s, "_[Hello");
+    assert_eq!(deroffer.rest(), "_[Hello");
     assert!(deroffer.output.take().is_empty());
 
-    deroffer.s = "'_]Hello".into();
+    deroffer.set_line("'_]Hello");
     assert!(deroffer.request_or_macro());
     assert!(!deroffer.refer);
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert_eq!(deroffer.output.take(), "]Hello");
 
-    deroffer.s = "'_.Hello".into();
+    deroffer.set_line("'_.Hello");
     assert!(deroffer.request_or_macro());
     assert!(deroffer.r#macro == 0);
-    assert_eq!(deroffer.s, "_.Hello");
+    assert_eq!(deroffer.rest(), "_.Hello");
     assert_eq!(deroffer.output.take(), "\n");
 
-    deroffer.s = ".SH".into();
+    deroffer.set_line(".SH");
     assert!(deroffer.request_or_macro());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert!(deroffer.output.take().is_empty());
 
-    deroffer.s = ".] Hello World".into();
+    deroffer.set_line(".] Hello World");
     assert!(deroffer.request_or_macro());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert_eq!(deroffer.output.take(), "Hello World");
 }
 
@@ -1500,49 +2769,49 @@ fn test_do_tbl() {
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
 
-    deroffer.s = "aaa(bbb);Hello World!".into();
+    deroffer.set_line("aaa(bbb);Hello World!");
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert_eq!(deroffer.s, ";Hello World!");
+    assert_eq!(deroffer.rest(), ";Hello World!");
     assert_eq!(deroffer.output.take(), "\n");
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
-    deroffer.s = "aaa(bbb;Hello World!".into();
+    deroffer.set_line("aaa(bbb;Hello World!");
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
-    deroffer.s = ";".into();
+    deroffer.set_line(";");
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert_eq!(deroffer.s, ";");
+    assert_eq!(deroffer.rest(), ";");
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
-    deroffer.s = "\n".into();
+    deroffer.set_line("\n");
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert_eq!(deroffer.s, "\n");
+    assert_eq!(deroffer.rest(), "\n");
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
     assert!(deroffer.do_tbl());
     assert!(deroffer.tbl_tab.is_empty());
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Options;
-    deroffer.s = "Tab(arg);".into();
+    deroffer.set_line("Tab(arg);");
     assert!(deroffer.do_tbl());
     assert_eq!(deroffer.tbl_tab, "a");
-    assert_eq!(deroffer.s, ";");
+    assert_eq!(deroffer.rest(), ";");
     assert_eq!(deroffer.tblstate, TblState::Format);
 
     // </Options>
@@ -1551,42 +2820,42 @@ fn test_do_tbl() {
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Format;
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("Hello World!");
     assert!(deroffer.do_tbl());
     assert_ne!(deroffer.tblstate, TblState::Data);
     assert_eq!(deroffer.output.take(), "\n");
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Format;
-    deroffer.s = "".into();
+    deroffer.set_line("");
     assert!(deroffer.do_tbl());
     assert_ne!(deroffer.tblstate, TblState::Data);
     assert_eq!(deroffer.output.take(), "\n");
-    assert!(deroffer.s.is_empty());
+    assert!(deroffer.rest().is_empty());
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Format;
-    deroffer.s = "Hello World!.foo bar!".into();
+    deroffer.set_line("Hello World!.foo bar!");
     assert!(deroffer.do_tbl());
     assert_eq!(deroffer.tblstate, TblState::Data);
-    assert_eq!(deroffer.s, ".foo bar!");
+    assert_eq!(deroffer.rest(), ".foo bar!");
     assert_eq!(deroffer.output.take(), "\n");
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Format;
-    deroffer.s = "\n".into();
+    deroffer.set_line("\n");
     assert!(deroffer.do_tbl());
     assert_ne!(deroffer.tblstate, TblState::Data);
-    assert_eq!(deroffer.s, "\n");
+    assert_eq!(deroffer.rest(), "\n");
     assert_eq!(deroffer.output.take(), "\n");
 
     let mut deroffer = Deroffer::new();
     deroffer.tblstate = TblState::Format;
-    deroffer.s = ".".into();
+    deroffer.set_line(".");
     assert!(deroffer.do_tbl());
     assert_eq!(deroffer.tblstate, TblState::Data);
-    assert_eq!(deroffer.s, ".");
+    assert_eq!(deroffer.rest(), ".");
     assert_eq!(deroffer.output.take(), "\n");
 
     // </Format>
@@ -1596,13 +2865,68 @@ fn test_do_tbl() {
     deroffer.tblstate = TblState::Data;
     deroffer.tbl_tab = "a".into();
 
-    deroffer.s = "HelloaWorld!".into();
+    deroffer.set_line("HelloaWorld!");
     assert!(deroffer.do_tbl());
     assert_eq!(deroffer.tblstate, TblState::Data);
-    assert_eq!(deroffer.s, "");
+    assert_eq!(deroffer.rest(), "");
     assert_eq!(deroffer.output.take(), "Hello\tWorld!");
 
     // </Data>
+
+    // <TextBlock>
+
+    let mut deroffer = Deroffer::new();
+    deroffer.tblstate = TblState::Data;
+    deroffer.set_line("\\-f\tT{\n");
+    assert!(deroffer.do_tbl());
+    assert_eq!(deroffer.tblstate, TblState::TextBlock { empty: true });
+    assert_eq!(deroffer.output.take(), "-f\t");
+
+    deroffer.set_line("Force removal\n");
+    assert!(deroffer.do_tbl());
+    assert_eq!(deroffer.tblstate, TblState::TextBlock { empty: false });
+    assert_eq!(deroffer.output.take(), "Force removal");
+
+    deroffer.set_line("T}\tyes\n");
+    assert!(deroffer.do_tbl());
+    assert_eq!(deroffer.tblstate, TblState::Data);
+    assert_eq!(deroffer.output.take(), "\tyes\n");
+
+    // </TextBlock>
+}
+
+#[test]
+fn test_deroff_tbl() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".TS
+tab(@);
+lb l
+lb l.
+Option@Meaning
+_
+\-a@T{
+Show
+.B all
+entries
+T}
+\-\-color@T{
+Colorize
+T}@\^
+.TE
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "\nOption\tMeaning\n-a\tShow all entries\n--color\tColorize\t\n"
+    );
+
+    // Without an options line
+    let mut deroffer = Deroffer::new();
+    deroffer.deroff(".TS\nl l.\n\\-v\tverbose\n.TE\n").unwrap();
+    assert_eq!(deroffer.get_output(), "\n\n-v\tverbose\n");
 }
 
 #[test]
@@ -1610,40 +2934,102 @@ fn test_spec() {
     // I create new deroffers for each test to reset the shared state
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "\\(Sdaaaa".into(); // `ð`
+    deroffer.set_line("\\(Sdaaaa"); // `ð`
     assert!(deroffer.spec());
     assert!(deroffer.specletter);
     assert_eq!(deroffer.output.take(), "ð");
-    assert_eq!(deroffer.s, "aaaa");
+    assert_eq!(deroffer.rest(), "aaaa");
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "\\(miaaaa".into(); // `-`
+    deroffer.set_line("\\(miaaaa"); // `-`
     assert!(deroffer.spec());
     assert!(!deroffer.specletter);
     assert_eq!(deroffer.output.take(), "-");
-    assert_eq!(deroffer.s, "aaaa");
+    assert_eq!(deroffer.rest(), "aaaa");
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "\\(aaaaaa".into();
+    deroffer.set_line("\\(XXaaaa");
     assert!(deroffer.spec());
     assert!(!deroffer.specletter);
     assert!(deroffer.output.take().is_empty());
-    assert_eq!(deroffer.s, "aaaa");
+    assert_eq!(deroffer.rest(), "aaaa");
 
     let mut deroffer = Deroffer::new();
-    deroffer.s = "\\%asdasdasd".into();
+    deroffer.set_line("\\%asdasdasd");
     assert!(deroffer.spec());
     assert!(deroffer.specletter);
     assert!(deroffer.output.take().is_empty());
-    assert_eq!(deroffer.s, "asdasdasd");
+    assert_eq!(deroffer.rest(), "asdasdasd");
+
+    let mut deroffer = Deroffer::new();
+    deroffer.set_line("Hello World!");
+    assert!(!deroffer.spec());
+    assert_eq!(deroffer.rest(), "Hello World!");
+    assert!(deroffer.output.take().is_empty());
+}
 
+#[test]
+fn test_spec_groff_char() {
     let mut deroffer = Deroffer::new();
-    deroffer.s = "Hello World!".into();
+    deroffer.set_line("\\(*aaaaa");
+    assert!(deroffer.spec());
+    assert!(deroffer.specletter);
+    assert_eq!(deroffer.output.take(), "α");
+    assert_eq!(deroffer.rest(), "aaaa");
+
+    deroffer.set_line("\\[bracketlefttp] Hello");
+    assert!(deroffer.spec());
+    assert!(!deroffer.specletter);
+    assert_eq!(deroffer.output.take(), "⎡");
+    assert_eq!(deroffer.rest(), " Hello");
+
+    deroffer.set_line("\\[u00E9]t\\[char233]");
+    assert!(deroffer.spec());
+    assert!(deroffer.specletter);
+    assert_eq!(deroffer.output.take(), "é");
+    assert_eq!(deroffer.rest(), "t\\[char233]");
+    deroffer.skip_char(1);
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "é");
+    assert!(deroffer.rest().is_empty());
+
+    deroffer.set_line("\\[u0065_0301]");
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "e\u{301}");
+
+    deroffer.set_line("\\(en\\[em]verbose");
+    assert!(deroffer.spec());
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "--");
+    assert_eq!(deroffer.rest(), "verbose");
+
+    deroffer.set_line("\\[e aa]");
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "e\u{301}");
+
+    deroffer.set_line("\\C'*b'\\N'65'");
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "β");
+    assert!(deroffer.spec());
+    assert_eq!(deroffer.output.take(), "A");
+    assert!(deroffer.rest().is_empty());
+
+    // Unterminated
+    deroffer.set_line("\\[u00E9");
+    assert!(!deroffer.spec());
+    deroffer.set_line("\\C'oops");
     assert!(!deroffer.spec());
-    assert_eq!(deroffer.s, "Hello World!");
     assert!(deroffer.output.take().is_empty());
 }
 
+#[test]
+fn test_text_unicode_escapes() {
+    let mut deroffer = Deroffer::new();
+    deroffer.set_line("\\[u00C9]diteur de sch\\('emas \\(*a\\(<=\\(*b");
+    assert!(deroffer.text());
+    assert_eq!(deroffer.output.take(), "Éditeur de schémas α≤β");
+}
+
 #[test]
 fn test_get_output() {
     let deroffer = Deroffer::new();
@@ -1657,11 +3043,11 @@ fn test_get_output() {
 fn test_not_whitespace() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "".to_owned();
+    deroffer.set_line("");
     assert_eq!(deroffer.not_whitespace(0), false);
     assert_eq!(deroffer.not_whitespace(9), false);
 
-    deroffer.s = "ab d".to_owned();
+    deroffer.set_line("ab d");
     // idx 2 = " ", should be false
     assert_eq!(deroffer.not_whitespace(2), false);
     assert_eq!(deroffer.not_whitespace(3), true);
@@ -1673,11 +3059,11 @@ fn test_str_at() {
 
     assert_eq!(deroffer.str_at(1), "");
 
-    deroffer.s = "ab cd".to_owned();
+    deroffer.set_line("ab cd");
     assert_eq!(deroffer.str_at(42), "");
     assert_eq!(deroffer.str_at(1), "b");
 
-    deroffer.s = "🗻".to_owned();
+    deroffer.set_line("🗻");
     assert_eq!(deroffer.str_at(0), "🗻");
     assert_eq!(deroffer.str_at(1), "");
 }
@@ -1688,7 +3074,7 @@ fn test_is_white() {
 
     assert_eq!(deroffer.is_white(1), false);
 
-    deroffer.s = "ab cd".to_owned();
+    deroffer.set_line("ab cd");
     assert_eq!(deroffer.is_white(42), false);
     assert_eq!(deroffer.is_white(1), false);
     assert_eq!(deroffer.is_white(2), true);
@@ -1700,69 +3086,69 @@ fn test_var() {
     let mut d = Deroffer::new();
 
     // "\n" successes
-    d.s = "\\n dyHello".to_owned();
+    d.set_line("\\n dyHello");
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, "Hello");
+    assert_eq!(d.rest(), "Hello");
 
-    d.s = "\\n(aaHello".to_owned();
+    d.set_line("\\n(aaHello");
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, "Hello");
+    assert_eq!(d.rest(), "Hello");
 
-    d.s = "\\n[skipme] Hello".to_owned();
+    d.set_line("\\n[skipme] Hello");
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, "] Hello");
+    assert_eq!(d.rest(), "] Hello");
 
-    d.s = "\\naHello".to_owned();
+    d.set_line("\\naHello");
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, "Hello");
+    assert_eq!(d.rest(), "Hello");
 
     // "\n" errors
-    d.s = "\\n".to_owned();
+    d.set_line("\\n");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "\\n");
+    assert_eq!(d.rest(), "\\n");
 
-    d.s = "\\n a".to_owned();
+    d.set_line("\\n a");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "\\n a");
+    assert_eq!(d.rest(), "\\n a");
 
-    d.s = "\\n da".to_owned();
+    d.set_line("\\n da");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "\\n da");
+    assert_eq!(d.rest(), "\\n da");
 
     // "\*" successes
 
-    d.s = "\\*(traaaaaaaaaaaaa".to_owned();
+    d.set_line("\\*(traaaaaaaaaaaaa");
     d.reg_table
         .insert("tr".to_owned(), "Hello World!".to_owned());
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, " World!");
+    assert_eq!(d.rest(), " World!");
     let o = d.output.take();
     assert!(o.contains("Hello"));
     d.output.set(o);
 
-    d.s = "\\*(aaHello World!".to_owned();
+    d.set_line("\\*(aaHello World!");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "Hello World!");
+    assert_eq!(d.rest(), "Hello World!");
 
     // ideal case, B is in reg_table
-    d.s = "\\*[test_reg]".to_owned();
+    d.set_line("\\*[test_reg]");
     d.reg_table
         .insert("test_reg".to_owned(), "It me!".to_owned());
     assert_eq!(d.var(), true);
-    assert_eq!(d.s, " me!");
+    assert_eq!(d.rest(), " me!");
     let o = d.output.take();
     assert!(o.contains("It"));
     d.output.set(o);
 
     // no "]"
-    d.s = "\\*[foo bar :)".to_owned();
+    d.set_line("\\*[foo bar :)");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "");
+    assert_eq!(d.rest(), "");
 
     // B not in reg_table
-    d.s = "\\*[foo bar]abcd".to_owned();
+    d.set_line("\\*[foo bar]abcd");
     assert_eq!(d.var(), false);
-    assert_eq!(d.s, "abcd");
+    assert_eq!(d.rest(), "abcd");
 }
 
 #[test]
@@ -1808,73 +3194,73 @@ fn test_condputs() {
 fn test_digit() {
     let mut deroffer = Deroffer::new();
 
-    deroffer.s = "0".to_owned();
+    deroffer.set_line("0");
     assert_eq!(deroffer.digit(0), true);
 
-    deroffer.s = "9".to_owned();
+    deroffer.set_line("9");
     assert_eq!(deroffer.digit(0), true);
 
-    deroffer.s = "".to_owned();
+    deroffer.set_line("");
     assert_eq!(deroffer.digit(1), false);
 
-    deroffer.s = "1".to_owned();
+    deroffer.set_line("1");
     assert_eq!(deroffer.digit(1), false);
 
-    deroffer.s = "a".to_owned();
+    deroffer.set_line("a");
     assert_eq!(deroffer.digit(0), false);
 
-    deroffer.s = " ".to_owned();
+    deroffer.set_line(" ");
     assert_eq!(deroffer.digit(0), false);
 }
 
 #[test]
 fn test_skip_char() {
     let mut d = Deroffer::new();
-    d.s = String::from("      Hello         World");
+    d.set_line("      Hello         World");
     d.skip_char(6);
-    assert_eq!(&d.s, "Hello         World");
+    assert_eq!(d.rest(), "Hello         World");
     d.skip_char(5);
-    assert_eq!(&d.s, "         World");
+    assert_eq!(d.rest(), "         World");
     d.skip_char(9);
-    assert_eq!(&d.s, "World");
+    assert_eq!(d.rest(), "World");
     d.skip_char(5);
-    assert_eq!(&d.s, "");
+    assert_eq!(d.rest(), "");
 }
 
 #[test]
 fn test_skip_leading_whitespace() {
     let mut d = Deroffer::new();
-    d.s = String::from("          Hello        World");
+    d.set_line("          Hello        World");
     d.skip_leading_whitespace();
-    assert_eq!(&d.s, "Hello        World");
+    assert_eq!(d.rest(), "Hello        World");
     d.skip_char(5);
-    assert_eq!(&d.s, "        World");
+    assert_eq!(d.rest(), "        World");
     d.skip_leading_whitespace();
-    assert_eq!(&d.s, "World");
+    assert_eq!(d.rest(), "World");
     d.skip_leading_whitespace();
-    assert_eq!(&d.s, "World");
+    assert_eq!(d.rest(), "World");
 }
 
 #[test]
 fn test_number() {
     let mut d = Deroffer::new();
 
-    d.s = String::from("4343xx7");
+    d.set_line("4343xx7");
     assert_eq!(d.number(), true);
     let o = d.output.take();
     assert_eq!(o, "4343".to_string());
     d.output.set(o);
 
-    d.s = String::from("__23");
+    d.set_line("__23");
     assert_eq!(d.number(), false);
 
-    d.s = String::from("-18.5");
+    d.set_line("-18.5");
     assert_eq!(d.number(), true);
     let o = d.output.take();
     assert_eq!(o, "4343-18".to_string());
     d.output.set(o);
 
-    d.s = String::from("+078t");
+    d.set_line("+078t");
     assert_eq!(d.number(), true);
 }
 
@@ -1889,3 +3275,338 @@ fn test_number() {
 //         p = pstats.Stats('fooprof')
 //         p.sort_stats('time').print_stats(100)
 //         #p.sort_stats('calls').print_callers(.5, 'startswith')
+
+#[test]
+fn test_mdoc() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".Dd March 1, 2020
+.Dt LS 1
+.Sh NAME
+.Nm ls
+.Nd list directory contents
+.Sh DESCRIPTION
+.Nm
+lists
+.Ar file
+arguments.
+.Bl -tag -width Ds
+.It Fl a
+Include entries starting with a dot, which
+.Nm ,
+hides otherwise.
+.It Fl o Ar file , Fl Fl output Ns = Ns Ar file
+Write to
+.Pa file .
+.It Xo
+.Fl Fl color
+.Op Ns = Ns Ar when
+.Xc
+Colorize, see
+.Xr dircolors 1 .
+.El
+.Sh SEE ALSO
+.Xr dir 1 ,
+.Xr vdir 1
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "\n\nNAME\nls - list directory contents\nDESCRIPTION\nls lists\nfile\narguments.\n\
+         -a\nInclude entries starting with a dot, which\nls,\nhides otherwise.\n\n\
+         -o file, --output=file\nWrite to\nfile.\n\n\
+         --color[=when]\nColorize, see\ndircolors(1).\n\n\
+         SEE ALSO\ndir(1),\nvdir(1)\n\n"
+    );
+}
+
+#[test]
+fn test_mdoc_enclosures() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".Op Fl v | Fl q
+.Pq Dq quoted , and so on .
+.Oo Fl x Oc Fl
+.Ic make Cm all , Ql \&.
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "[-v | -q]\n(\"quoted, and so on\").\n[-x] -\nmake all, '.'\n\n"
+    );
+
+    let mut deroffer = Deroffer::with_options(DeroffOptions::new().font_annotations(true));
+    deroffer.deroff(".It Fl o Ar file\n").unwrap();
+    assert_eq!(deroffer.get_output(), "\n\\fB-o\\fR \\fIfile\\fR\n\n");
+}
+
+#[test]
+fn test_an_ext() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".SY grep
+.OP \-e pattern
+.OP \-v
+.RI [ file ]
+.YS
+.TP
+.B \-v
+.TQ
+.B \-\-invert\-match
+Invert the sense of matching.
+See
+.UR https://www.gnu.org/software/grep/
+the manual
+.UE ,
+or write to
+.MT bug\-grep@gnu.org
+.ME .
+.EX
+$ grep \-v foo bar
+.EE
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "\ngrep\n[-e pattern]\n[-v]\n[ file ]\n-v, --invert-match\nInvert the sense of matching.\n\
+         See\nthe manual <https://www.gnu.org/software/grep/>,\n\
+         or write to <bug-grep@gnu.org>.\n\n$ grep -v foo bar\n"
+    );
+
+    let mut deroffer = Deroffer::with_options(DeroffOptions::new().font_annotations(true));
+    deroffer.deroff(".OP \\-o file\n").unwrap();
+    assert_eq!(deroffer.get_output(), "[\\fB-o\\fR \\fIfile\\fR]\n\n");
+}
+
+#[test]
+fn test_example() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#"Run it as
+.EX
+$ grep \-r foo
+    for dir in *;   do
+
+
+
+        grep foo "$dir"
+    done
+.EE
+and then
+
+
+
+done.
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "Run it as\n\n$ grep -r foo\n    for dir in *;   do\n\n\n\n        grep foo \"$dir\"\n    done\n\
+         \nand then\ndone.\n\n"
+    );
+}
+
+#[test]
+fn test_utf8_safety() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(".SH ÜBERSICHT\n\\é \\*(éa \\n(éa\n.ds éé ü\n\\*(éé\n.tr éü\n.tr ö\nélan övé\n")
+        .unwrap();
+    assert_eq!(deroffer.get_output(), "ÜBERSICHT\né  \n\nüülan  vü\n\n");
+
+    deroffer.set_line("");
+    assert!(deroffer.do_line());
+
+    deroffer.deroff(".TS\ntab(é);\nl l.\naéb\n.TE\n").unwrap();
+    assert_eq!(deroffer.get_output(), "\na\tb\n");
+}
+
+#[test]
+fn test_deroff_error() {
+    let mut deroffer = Deroffer::new();
+    assert_eq!(
+        deroffer.deroff(".ds x a\\*x\nfine\n\\*x\n"),
+        Err(DeroffError {
+            line: 3,
+            message: "string `x` expands into itself".to_owned()
+        })
+    );
+    assert_eq!(
+        DeroffError {
+            line: 3,
+            message: "oops".to_owned()
+        }
+        .to_string(),
+        "line 3: oops"
+    );
+}
+
+/// Time deroffing the fixtures, along with the pages in `DEROFF_BENCH_DIR` if
+/// it is set. Run with
+/// `cargo test --release bench_deroff -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_deroff() {
+    use std::time::Instant;
+
+    let mut paths: Vec<_> = std::fs::read_dir("fixtures")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("1".as_ref()))
+        .collect();
+    if let Some(dir) = std::env::var_os("DEROFF_BENCH_DIR") {
+        paths.extend(
+            std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path()),
+        );
+    }
+    let pages: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let mut bytes = Vec::new();
+            let file = File::open(path).ok()?;
+            if path.extension() == Some("gz".as_ref()) {
+                GzDecoder::new(file).read_to_end(&mut bytes).ok()?;
+            } else {
+                io::BufReader::new(file).read_to_end(&mut bytes).ok()?;
+            }
+            Some(crate::lossy_unicode(bytes))
+        })
+        .collect();
+
+    let rounds = if pages.len() > 10 { 1 } else { 100 };
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for page in &pages {
+            let mut deroffer = Deroffer::new();
+            let _ = deroffer.deroff(page);
+            deroffer.get_output();
+        }
+    }
+    println!(
+        "deroffed {} pages {} times in {:?}",
+        pages.len(),
+        rounds,
+        start.elapsed()
+    );
+}
+
+#[test]
+fn test_deroff_options() {
+    let page = ".SH SYNOPSIS\nls [-a]\n.SH DESCRIPTION\nList.\n.BL\n.LI\nitem\n.LE\n\n\n\nEnd.\n";
+    let deroff = |options| {
+        let mut deroffer = Deroffer::with_options(options);
+        deroffer.deroff(page).unwrap();
+        deroffer.get_output()
+    };
+
+    assert_eq!(deroff(DeroffOptions::new()), "DESCRIPTION\nList.\nEnd.\n\n");
+    assert_eq!(
+        deroff(DeroffOptions::new().skip_headers(true)),
+        "List.\nEnd.\n\n"
+    );
+    assert_eq!(
+        deroff(DeroffOptions::new().keep_synopsis(true)),
+        "SYNOPSIS\nls [-a]\nDESCRIPTION\nList.\nEnd.\n\n"
+    );
+    assert_eq!(
+        deroff(DeroffOptions::new().keep_lists(true)),
+        "DESCRIPTION\nList.\nitem\nEnd.\n\n"
+    );
+    assert_eq!(
+        deroff(DeroffOptions::new().collapse_blank_lines(false)),
+        "DESCRIPTION\nList.\n\n\n\n\nEnd.\n\n"
+    );
+}
+
+#[test]
+fn test_requests_on_macros_and_strings() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".de Xx
+.SH HIDDEN
+..
+shown
+.am Xx EN
+hidden
+.EN
+.ig \" comment
+.SH HIDDEN
+..
+.ig xx
+hidden
+.xx
+.als Hd SH
+.Hd HEADER
+text
+"#,
+        )
+        .unwrap();
+    assert_eq!(deroffer.get_output(), "\nshown\n\nHEADER\ntext\n\n");
+
+    deroffer
+        .deroff(".ds a one\n.as a \" two\n.rn a b\n.als c b\n.rm b\n")
+        .unwrap();
+    assert_eq!(deroffer.reg_table.get("a"), None);
+    assert_eq!(deroffer.reg_table.get("b"), None);
+    assert_eq!(deroffer.reg_table.get("c").unwrap(), "one two");
+}
+
+#[test]
+fn test_escape_character() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#".ec @
+@fBbold@fR C:\dir
+.eo
+\fB C:\dir
+.ec
+\fBback\fR
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "bold C:\\dir\n\\fB C:\\dir\nback\n\n"
+    );
+}
+
+#[test]
+fn test_requests_by_whole_name() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(".defcolor red rgb 1 0 0\n.ecs\n\\fBshown\\fR\n.ecr\ntext\n")
+        .unwrap();
+    assert_eq!(deroffer.get_output(), "\n\nshown\n\ntext\n\n");
+}
+
+#[test]
+fn test_line_continuation() {
+    let mut deroffer = Deroffer::new();
+    deroffer
+        .deroff(
+            r#"one \
+two\\
+.B three\c
+four\c ignored
+\fBfive\fR
+\zo\(:aver\o'e\(aa'd \R'x 1'\X'ps: exec'\Y[foo]\D'l 1i 0'\k(xx\m[red]done
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        deroffer.get_output(),
+        "one two\\\nthreefourfive\nävered done\n\n"
+    );
+}
@@ -2,7 +2,6 @@
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read, Write};
-use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{env, fmt};
@@ -193,8 +192,6 @@ fn fish_escape_single_quote(s: &str) -> String {
     }
 }
 
-// Rust port replaced with String::from_utf8_lossy(s.as_bytes())
-//
 // # Make a string Unicode by attempting to decode it as latin-1, or UTF8. See #658
 // def lossy_unicode(s):
 //     # All strings are unicode in Python 3
@@ -209,16 +206,20 @@ fn fish_escape_single_quote(s: &str) -> String {
 //         pass
 //     return s.decode('latin-1', 'ignore')
 
-// #[test]
-// fn test_lossy_unicode() {
-//     let bytes = b"123 456";
-//     let s = lossy_unicode(bytes);
-//     assert_eq!("123 456", s);
-//
-//     let bad_bytes = &[255];
-//     let bad_s = lossy_unicode(bad_bytes);
-//     assert_eq!("�", bad_s);
-// }
+/// Decode the raw bytes of a man page. Pages are UTF-8 nowadays, but plenty of
+/// older ones (e.g. `fixtures/qelectrotech.1`) are still latin-1, and every
+/// byte sequence is valid latin-1.
+fn lossy_unicode(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|err| err.into_bytes().into_iter().map(char::from).collect())
+}
+
+#[test]
+fn test_lossy_unicode() {
+    assert_eq!(lossy_unicode(b"123 456".to_vec()), "123 456");
+    assert_eq!(lossy_unicode("éditeur".as_bytes().to_vec()), "éditeur");
+    assert_eq!(lossy_unicode(b"\xe9diteur".to_vec()), "éditeur");
+}
 
 const MAX_DESCRIPTION_WIDTH: usize = 78;
 const TRUNCATION_SUFFIX: char = '…';
@@ -227,6 +228,78 @@ fn char_len(string: &str) -> usize {
     string.chars().count()
 }
 
+/// The negated forms of the long options in `options`, with the options they
+/// negate, like `--no-color` for `--[no-]color`, or for an option whose
+/// description says that it "can be negated with --no-color".
+fn negated_options(options: &str, description: &str) -> Vec<(String, String)> {
+    let mut out: Vec<_> = regex!(r"--\[no(-?)\]([\w-]+)")
+        .captures_iter(options)
+        .map(|caps| {
+            (
+                format!("--no{}{}", &caps[1], &caps[2]),
+                format!("--{}", &caps[2]),
+            )
+        })
+        .collect();
+    let option = regex!(r"(?:^|[\s,])(--[[:alnum:]][\w-]*)")
+        .captures(options)
+        .map(|caps| caps[1].to_owned());
+    let negation = regex!(r"(?i)\b(?:negated|disabled|turned off)\b([^.]*)").captures(description);
+    if let (Some(option), Some(negation)) = (option, negation) {
+        // Only the option itself negated, "can be negated with --no-" leaves
+        // out its name
+        let name = &option[2..];
+        let negated = regex!(r"--no(-?)([\w-]*)")
+            .captures_iter(&negation[1])
+            .find_map(|caps| match &caps[2] {
+                "" => Some(format!("--no-{}", name)),
+                negated if negated == name => Some(format!("--no{}{}", &caps[1], name)),
+                _ => None,
+            });
+        out.extend(negated.map(|negated| (negated, option)));
+    }
+    out
+}
+
+#[test]
+fn test_negated_options() {
+    assert_eq!(
+        negated_options("-n, --[no-]verify", ""),
+        vec![("--no-verify".to_owned(), "--verify".to_owned())]
+    );
+    assert_eq!(
+        negated_options("--[no]pager", ""),
+        vec![("--nopager".to_owned(), "--pager".to_owned())]
+    );
+    assert_eq!(
+        negated_options(
+            "--color",
+            "Colorize the output. This can be negated with --no-color."
+        ),
+        vec![("--no-color".to_owned(), "--color".to_owned())]
+    );
+    assert_eq!(
+        negated_options("-q, --quiet", "Can be negated by prepending --no- to it"),
+        vec![("--no-quiet".to_owned(), "--quiet".to_owned())]
+    );
+    assert_eq!(negated_options("--no-color", "Disable colors"), vec![]);
+    assert_eq!(
+        negated_options(
+            "--color",
+            "Colorize, which is disabled when --no-tty is given"
+        ),
+        vec![]
+    );
+    assert_eq!(
+        negated_options("--pager", "Use a pager, disabled by --no-tty or --nopager"),
+        vec![("--nopager".to_owned(), "--pager".to_owned())]
+    );
+    assert_eq!(
+        negated_options("--color=WHEN", "Colorize the output"),
+        vec![]
+    );
+}
+
 fn fish_options(options: &str, existing_options: &mut HashSet<String>) -> Vec<String> {
     let mut out = vec![];
 
@@ -252,6 +325,13 @@ fn fish_options(options: &str, existing_options: &mut HashSet<String>) -> Vec<St
             ("l", 2)
         } else if option.starts_with("-") {
             (if option.len() == 2 { "s" } else { "o" }, 1)
+        } else if regex!(r"^\+[[:alnum:]][\w-]*$").is_match(&option)
+            && !regex!(r"^\+[[:upper:]_]{2,}").is_match(&option)
+            && regex!(r"(?:^|\s)\+").is_match(options)
+        {
+            // The toggles of X11 programs, like `+rv`, are arguments for fish,
+            // but not placeholders like the `+FIRST_PAGE` of pr
+            ("a", 0)
         } else {
             continue;
         };
@@ -318,6 +398,19 @@ fn test_fish_options() {
             .collect::<HashSet<_>>(),
         existing_options,
     );
+
+    assert_eq!(
+        fish_options("-rv, +rv", &mut HashSet::new()),
+        vec!["-o rv".to_owned(), "-a +rv".to_owned()]
+    );
+    assert_eq!(
+        fish_options("+FIRST_PAGE[:LAST_PAGE]", &mut HashSet::new()),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        fish_options("-mattr=a1,+a2", &mut HashSet::new()),
+        vec!["-o mattr".to_owned()]
+    );
 }
 
 /// # Panics
@@ -358,8 +451,8 @@ fn test_string_truncation() {
 fn truncated_description(description: &str) -> String {
     let sentences = description.replace(r"\'", "'").replace(r"\.", ".");
 
-    let mut sentences = sentences
-        .split(".")
+    let mut sentences = split_sentences(&sentences)
+        .into_iter()
         .filter(|sentence| !sentence.trim().is_empty());
 
     let out = sentences.next().unwrap_or_default();
@@ -380,13 +473,41 @@ fn truncated_description(description: &str) -> String {
         }
     }
 
-    fish_escape_single_quote(&out.trim_end_matches('.'))
+    fish_escape_single_quote(out.strip_suffix('.').unwrap_or(&out))
+}
+
+/// Split `text` at the periods that end a sentence, those after a word and
+/// before a space, unlike the `.` of "starting with ." or those of "e.g.,".
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '.'
+            && text[..i].ends_with(|c: char| !c.is_whitespace())
+            && text[i + 1..].chars().next().is_none_or(char::is_whitespace)
+        {
+            sentences.push(&text[start..i]);
+            start = i + 1;
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
 }
 
 #[test]
 fn test_truncated_description() {
     assert_eq!(truncated_description(r"\'\."), r"'\''");
 
+    assert_eq!(
+        truncated_description("do not ignore entries starting with ."),
+        "'do not ignore entries starting with .'"
+    );
+
+    assert_eq!(
+        truncated_description("Use version 1.5, e.g., for old files. Or not."),
+        "'Use version 1.5, e.g., for old files.  Or not'"
+    );
+
     assert_eq!(
         truncated_description(r"Don't use this command."),
         r"'Don\'t use this command'"
@@ -420,41 +541,455 @@ fn test_truncated_description() {
     );
 }
 
-struct Completions<'a> {
-    cmdname: &'a str,
-    // TODO should we store the whole built_command here?
-    built_command_output: Vec<String>,
+/// The options found in a man page, to be written as `complete` commands for
+/// any of the commands it documents.
+struct Completions {
+    entries: Vec<CompletionEntry>,
     existing_options: HashSet<String>,
+    /// The operands in SYNOPSIS, if the page has one
+    operands: Option<Vec<String>>,
+    /// What option arguments and operands complete to
+    placeholders: placeholders::Placeholders,
 }
 
-impl<'a> Completions<'a> {
-    fn new(cmdname: &'a str) -> Completions {
+/// An option and its description.
+struct CompletionEntry {
+    /// The arguments to `complete`, e.g. `-s f -l force`
+    options: Vec<String>,
+    /// The placeholder for the option's argument, e.g. `FILE`, or `[WHEN]`
+    /// if the argument is optional
+    argument: Option<String>,
+    /// The description as on the page, it is truncated on output
+    description: String,
+}
+
+impl CompletionEntry {
+    /// Which of `commands` the description says the option is for alone, as
+    /// in "(gunzip only)" or "only when invoked as zcat", see `only_for_regex`.
+    fn only_for<'c>(&self, commands: &[(&'c str, regex::Regex)]) -> Option<&'c str> {
+        commands
+            .iter()
+            .find(|(_, re)| re.is_match(&self.description))
+            .map(|(command, _)| *command)
+    }
+}
+
+/// What a description says to be for `command` alone.
+fn only_for_regex(command: &str) -> regex::Regex {
+    let only_for = format!(
+        r"(?i)\(\s*{0}\s+only\s*\)|\bonly (for|with|when (run|invoked|called) as) {0}\b",
+        regex::escape(command)
+    );
+    regex::Regex::new(&only_for).unwrap()
+}
+
+impl Completions {
+    fn new() -> Completions {
         Completions {
-            cmdname,
-            built_command_output: Vec::new(),
+            entries: Vec::new(),
             existing_options: HashSet::new(),
+            operands: None,
+            placeholders: placeholders::Placeholders::default(),
         }
     }
 
+    /// Complete option arguments and operands with `placeholders` rather than
+    /// the built-in ones alone.
+    fn set_placeholders(&mut self, placeholders: placeholders::Placeholders) {
+        self.placeholders = placeholders;
+    }
+
     fn add(&mut self, option_name: &str, option_desc: &str) {
-        let fish_options = fish_options(option_name, &mut self.existing_options);
+        self.add_with_argument(option_name, None, option_desc);
+    }
+
+    /// Like `add`, for an option taking an argument, see `CompletionEntry`.
+    fn add_with_argument(&mut self, option_name: &str, argument: Option<&str>, option_desc: &str) {
+        let negated_options = negated_options(option_name, option_desc);
+        let option_name = regex!(r"--\[no-?\]").replace_all(option_name, "--");
+        let options = fish_options(&option_name, &mut self.existing_options);
+
+        if !options.is_empty() {
+            // Like the `--no-color` that SYNOPSIS shows
+            let description = match regex!(r"^--no-(\S+)$").captures(&option_name) {
+                Some(caps) if option_desc.is_empty() => {
+                    let option = format!("-l {}", fish_escape_single_quote(&caps[1]));
+                    match self.existing_options.contains(&option) {
+                        true => format!("Negate --{}", &caps[1]),
+                        false => String::new(),
+                    }
+                }
+                _ => option_desc.to_owned(),
+            };
+            self.entries.push(CompletionEntry {
+                options,
+                argument: argument.map(str::to_owned),
+                description,
+            });
+        }
+
+        for (negated, option) in negated_options {
+            let options = fish_options(&negated, &mut self.existing_options);
+            if !options.is_empty() {
+                self.entries.push(CompletionEntry {
+                    options,
+                    argument: None,
+                    description: format!("Negate {}", option),
+                });
+            }
+        }
+    }
 
-        if fish_options.is_empty() {
-            return;
+    /// Add the options of the SYNOPSIS section that are missing, without a
+    /// description, and the arguments that the others are missing. Its
+    /// operands tell whether the command takes files.
+    fn add_synopsis(&mut self, synopsis: synopsis::Synopsis) {
+        self.operands = Some(synopsis.operands.clone());
+        // Pages without `--` options that show one like `-display name`, or
+        // describe one like `-iconic`, have old style options, rather than
+        // groups of short options like `-Ccsu`
+        let old_style = !self
+            .existing_options
+            .iter()
+            .any(|option| option.starts_with("-l "))
+            && !synopsis
+                .options
+                .iter()
+                .any(|option| option.name.starts_with("--"))
+            && synopsis.options.iter().any(|option| {
+                option.is_old_style()
+                    || fish_options(&option.name, &mut HashSet::new())
+                        .iter()
+                        .any(|name| name.starts_with("-o ") && self.existing_options.contains(name))
+            });
+        for option in synopsis.options {
+            let names = match option.bundled() {
+                // An option described with an argument takes the rest of the
+                // group, as `-O` does the `level` of `-Olevel`
+                Some(mut names) if self.has_argument(&names[0]) => {
+                    names.truncate(1);
+                    names
+                }
+                // Unless it is an old style option with a description
+                Some(names)
+                    if !old_style
+                        && fish_options(&option.name, &mut HashSet::new())
+                            .iter()
+                            .all(|name| !self.existing_options.contains(name)) =>
+                {
+                    names
+                }
+                _ => vec![option.name],
+            };
+            for name in names {
+                let fish_option = fish_options(&name, &mut HashSet::new());
+                let entry = self.entries.iter_mut().find(|entry| {
+                    fish_option
+                        .iter()
+                        .all(|option| entry.options.contains(option))
+                });
+                match entry {
+                    Some(entry) => {
+                        if entry.argument.is_none() {
+                            entry.argument = option.argument.clone();
+                        }
+                    }
+                    None => self.add_with_argument(&name, option.argument.as_deref(), ""),
+                }
+            }
         }
+    }
+
+    /// Whether `option`, like `-O`, was added with an argument.
+    fn has_argument(&self, option: &str) -> bool {
+        let fish_option = fish_options(option, &mut HashSet::new());
+        self.entries.iter().any(|entry| {
+            entry.argument.is_some()
+                && fish_option
+                    .iter()
+                    .all(|option| entry.options.contains(option))
+        })
+    }
+
+    /// The completions, unless no options were found.
+    fn build(self) -> Option<Completions> {
+        Some(self).filter(|c| !c.entries.is_empty())
+    }
+
+    /// The `complete` commands for `cmdname`.
+    fn to_fish(&self, cmdname: &str) -> String {
+        self.entries_to_fish(cmdname, self.entries.iter())
+    }
 
-        self.built_command_output.push(complete_command(
-            &fish_escape_single_quote(self.cmdname),
-            fish_options,
-            &truncated_description(option_desc),
-        ));
+    /// The `complete` commands for each of `commands`, which a page documents
+    /// together. The first is the main one, which the others wrap, unless
+    /// the page has options for only one of them: then each gets a copy with
+    /// the options that apply to it.
+    fn to_fish_for_each(&self, commands: &[&str]) -> Vec<String> {
+        let only_for_res: Vec<_> = commands
+            .iter()
+            .map(|&command| (command, only_for_regex(command)))
+            .collect();
+        let only_for: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| entry.only_for(&only_for_res))
+            .collect();
+        if only_for.iter().all(Option::is_none) {
+            let main = fish_escape_single_quote(commands[0]);
+            return std::iter::once(self.entries_to_fish(commands[0], self.entries.iter()))
+                .chain(commands[1..].iter().map(|command| {
+                    format!(
+                        "complete -c {} --wraps {}",
+                        fish_escape_single_quote(command),
+                        main
+                    )
+                }))
+                .collect();
+        }
+        commands
+            .iter()
+            .map(|&command| {
+                let entries = self.entries.iter().zip(&only_for);
+                let entries = entries
+                    .filter(|(_, only_for)| only_for.unwrap_or(command) == command)
+                    .map(|(entry, _)| entry);
+                self.entries_to_fish(command, entries)
+            })
+            .collect()
     }
 
-    fn build(self) -> Option<String> {
-        Some(self.built_command_output.join("\n")).filter(|c| !c.is_empty())
+    fn entries_to_fish<'a>(
+        &self,
+        cmdname: &str,
+        entries: impl Iterator<Item = &'a CompletionEntry>,
+    ) -> String {
+        let cmdname = fish_escape_single_quote(cmdname);
+        entries
+            .map(|entry| {
+                let mut args = entry.options.clone();
+                if let Some(arg) = entry.argument.as_ref().filter(|arg| !arg.starts_with('[')) {
+                    args.push("-r".to_owned());
+                    args.extend(self.placeholders.arguments(arg).map(str::to_owned));
+                }
+                complete_command(&cmdname, args, &truncated_description(&entry.description))
+            })
+            .chain(self.operands_to_fish(&cmdname))
+            .join("\n")
+    }
+
+    /// The `complete` commands for the operands, that don't allow files unless
+    /// the command takes some. An operand takes what an option argument with
+    /// its name would, and files if that is unknown.
+    fn operands_to_fish(&self, cmdname: &str) -> Vec<String> {
+        let operands = match &self.operands {
+            Some(operands) => operands,
+            None => return Vec::new(),
+        };
+        let arguments: Vec<_> = operands
+            .iter()
+            .map(|operand| self.placeholders.arguments(operand))
+            .collect();
+        let takes_files = arguments
+            .iter()
+            .any(|arguments| arguments.is_none_or(|arguments| arguments.contains("-F")));
+        let no_files = Some(format!("complete -c {} -f", cmdname)).filter(|_| !takes_files);
+        // The no-files command above stands for the `-f` of each
+        let values = arguments
+            .into_iter()
+            .flatten()
+            .map(|arguments| {
+                arguments
+                    .strip_prefix("-f")
+                    .map_or(arguments, str::trim_start)
+            })
+            .filter(|values| !values.is_empty() && !values.contains("-F"))
+            .unique()
+            .map(|values| format!("complete -c {} {}", cmdname, values));
+        no_files.into_iter().chain(values).collect()
     }
 }
 
+#[test]
+fn test_add_synopsis_options() {
+    let mut completions = Completions::new();
+    completions.add("-c, --complement", "Use the complement of SET1");
+    completions.add("-o", "Write to file");
+    completions.add("-version", "Print the version");
+    let manpage = ".SH SYNOPSIS\n\
+                   tr [-Ccsu] [-o file] [-version] -d string1\n\
+                   .SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("tr"),
+        "complete -c tr -s c -l complement --description 'Use the complement of SET1'\n\
+         complete -c tr -s o -r -F --description 'Write to file'\n\
+         complete -c tr -o version --description 'Print the version'\n\
+         complete -c tr -s C\n\
+         complete -c tr -s s\n\
+         complete -c tr -s u\n\
+         complete -c tr -s d\n\
+         complete -c tr -f"
+    );
+
+    let mut completions = Completions::new();
+    completions.add("-9", "Kill for sure");
+    let manpage = ".SH SYNOPSIS\nkill [-9] PID...\nkill -l [NUMBER]\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("kill"),
+        "complete -c kill -s 9 --description 'Kill for sure'\n\
+         complete -c kill -s l\n\
+         complete -c kill -f\n\
+         complete -c kill -a '(__fish_complete_pids)'"
+    );
+
+    let mut completions = Completions::new();
+    completions.add("-R", "Operate recursively");
+    let manpage = ".SH SYNOPSIS\nchown [-R] OWNER FILE...\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("chown"),
+        "complete -c chown -s R --description 'Operate recursively'"
+    );
+
+    let mut completions = Completions::new();
+    completions.add("-p", "Remove the parents too");
+    let manpage = ".SH SYNOPSIS\nrmdir [-p] DIRECTORY...\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("rmdir"),
+        "complete -c rmdir -s p --description 'Remove the parents too'\n\
+         complete -c rmdir -f\n\
+         complete -c rmdir -a '(__fish_complete_directories)'"
+    );
+}
+
+#[test]
+fn test_placeholders_override() {
+    let mut completions = Completions::new();
+    completions.add("--pid=PID", "Send to this process");
+    let manpage = ".SH SYNOPSIS\nsvc [--pid=PID] SERVICE...\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("svc"),
+        "complete -c svc -l pid -r -f -a '(__fish_complete_pids)' --description 'Send to this process'"
+    );
+
+    completions.set_placeholders(
+        placeholders::Placeholders::read(
+            "service -f -a '(__fish_systemctl_services)'\npid -f\n".as_bytes(),
+        )
+        .unwrap(),
+    );
+    assert_eq!(
+        completions.to_fish("svc"),
+        "complete -c svc -l pid -r -f --description 'Send to this process'\n\
+         complete -c svc -f\n\
+         complete -c svc -a '(__fish_systemctl_services)'"
+    );
+}
+
+#[test]
+fn test_add_synopsis_attached_argument() {
+    for synopsis in ["[\\-H] [\\-O\\fIlevel\\fR]", "[-H] [-Olevel]"] {
+        let mut completions = Completions::new();
+        completions.add_with_argument("-O", Some("level"), "Enable query optimisation");
+        completions.add("--help", "Print a summary of the options");
+        let manpage = format!(
+            ".SH SYNOPSIS\nfind {} [path...]\n.SH DESCRIPTION\n",
+            synopsis
+        );
+        completions.add_synopsis(synopsis::Synopsis::from_manpage(&manpage).unwrap());
+        assert_eq!(
+            completions.to_fish("find"),
+            "complete -c find -s O -r --description 'Enable query optimisation'\n\
+             complete -c find -l help --description 'Print a summary of the options'\n\
+             complete -c find -s H"
+        );
+    }
+}
+
+#[test]
+fn test_add_old_style_synopsis_options() {
+    let mut completions = Completions::new();
+    completions.add("-display", "The X server to contact");
+    let manpage = ".SH SYNOPSIS\n\
+                   xev [-display displayname] [-root] [-rv] [+rv]\n\
+                   .SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("xev"),
+        "complete -c xev -o display -r --description 'The X server to contact'\n\
+         complete -c xev -o root\n\
+         complete -c xev -o rv\n\
+         complete -c xev -a +rv\n\
+         complete -c xev -f"
+    );
+
+    let mut completions = Completions::new();
+    completions.add("-iconic", "Start up in the iconic state");
+    let manpage = ".SH SYNOPSIS\nxclock [-iconic] [-rv]\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("xclock"),
+        "complete -c xclock -o iconic --description 'Start up in the iconic state'\n\
+         complete -c xclock -o rv\n\
+         complete -c xclock -f"
+    );
+}
+
+#[test]
+fn test_negatable_options() {
+    let mut completions = Completions::new();
+    completions.add("-n, --[no-]verify", "Run the hooks");
+    completions.add("--color", "Colorize, which can be negated with --no-color");
+    completions.add("--tags", "Fetch all tags");
+    let manpage = ".SH SYNOPSIS\ngit-fetch [--[no-]tags] [--no-verify]\n.SH DESCRIPTION\n";
+    completions.add_synopsis(synopsis::Synopsis::from_manpage(manpage).unwrap());
+    assert_eq!(
+        completions.to_fish("git-fetch"),
+        "complete -c git-fetch -s n -l verify --description 'Run the hooks'\n\
+         complete -c git-fetch -l no-verify --description 'Negate --verify'\n\
+         complete -c git-fetch -l color --description 'Colorize, which can be negated with --no-color'\n\
+         complete -c git-fetch -l no-color --description 'Negate --color'\n\
+         complete -c git-fetch -l tags --description 'Fetch all tags'\n\
+         complete -c git-fetch -l no-tags --description 'Negate --tags'\n\
+         complete -c git-fetch -f"
+    );
+}
+
+#[test]
+fn test_completions_for_each_command() {
+    let mut completions = Completions::new();
+    completions.add("-c --stdout", "Write to standard output.");
+    completions.add("-d", "Decompress.");
+    assert_eq!(
+        completions.to_fish_for_each(&["gzip", "gunzip"]),
+        [
+            "complete -c gzip -s c -l stdout --description 'Write to standard output'\n\
+             complete -c gzip -s d --description Decompress",
+            "complete -c gunzip --wraps gzip",
+        ]
+    );
+
+    completions.add("-k", "Keep the compressed file (gunzip only).");
+    completions.add("-N", "Only when invoked as zcat: keep the name.");
+    assert_eq!(
+        completions.to_fish_for_each(&["gzip", "gunzip", "zcat"]),
+        [
+            "complete -c gzip -s c -l stdout --description 'Write to standard output'\n\
+             complete -c gzip -s d --description Decompress",
+            "complete -c gunzip -s c -l stdout --description 'Write to standard output'\n\
+             complete -c gunzip -s d --description Decompress\n\
+             complete -c gunzip -s k --description 'Keep the compressed file (gunzip only)'",
+            "complete -c zcat -s c -l stdout --description 'Write to standard output'\n\
+             complete -c zcat -s d --description Decompress\n\
+             complete -c zcat -s N --description 'Only when invoked as zcat: keep the name'",
+        ]
+    );
+}
+
 /// Generate fish `complete` command.
 fn complete_command(cmdname: &str, args: Vec<String>, description: &str) -> String {
     let mut out = format!("complete -c {} {}", cmdname, args.join(" "));
@@ -526,9 +1061,16 @@ fn remove_groff_formatting(data: &str) -> Cow<str> {
     // let data = regex!(r##".PD( \d+)"##).replace_all(&data, "");
     // data.to_string()
     // using regex is twice as fast as manual replace
-    let re1 = regex!(
-        r"\\fI|\\fP|\\f1|\\fB|\\fR|\\e|\.BI|\.BR|0\.5i|\.rb|\\\^|\{ | \}|\.B|\.I|\f|(.PD( \d+))"
-    );
+    match remove_groff_formatting_except_fonts(data) {
+        Cow::Borrowed(s) => remove_fonts(s),
+        Cow::Owned(s) => Cow::Owned(remove_fonts(&s).into_owned()),
+    }
+}
+
+/// Like `remove_groff_formatting`, but keeps the `\fB`, `\fI`, `\fR`, `\fP`
+/// and `\f1` font escapes so `split_option_header` can use them.
+fn remove_groff_formatting_except_fonts(data: &str) -> Cow<'_, str> {
+    let re1 = regex!(r"\\e|\.BI|\.BR|0\.5i|\.rb|\\\^|\{ | \}|\.B|\.I|\f|(.PD( \d+))");
     let re2 = regex!(r"\\-");
     let re3 = regex!(r"\(cq");
     match re1.replace_all(&data, "") {
@@ -540,6 +1082,74 @@ fn remove_groff_formatting(data: &str) -> Cow<str> {
     }
 }
 
+fn remove_fonts(data: &str) -> Cow<'_, str> {
+    regex!(r"\\f[IPB1R]").replace_all(data, "")
+}
+
+/// Split an option header such as `\fB--output\fR=\fIFILE\fR` into the option
+/// names and the placeholder for its argument, going by the fonts: names are
+/// set in bold, placeholders in italics. Without fonts, all of it is names.
+fn split_option_header(header: &str) -> (String, Option<String>) {
+    let options = deroff::DeroffOptions::new().font_annotations(true);
+    let line = format!("\\&{}\n", header);
+    let mut deroffer = deroff::Deroffer::with_options(options);
+    match deroffer.deroff(&line) {
+        Ok(()) => option_header_from_spans(&deroff::font_spans(&deroffer.get_output())),
+        Err(_) => (remove_fonts(header).into_owned(), None),
+    }
+}
+
+fn option_header_from_spans(spans: &[(deroff::Font, String)]) -> (String, Option<String>) {
+    let mut names = String::new();
+    let mut argument = None;
+    for (font, text) in spans {
+        match font {
+            deroff::Font::Italic | deroff::Font::BoldItalic if !text.trim().starts_with('-') => {
+                // Keep the names on either side of the placeholder apart
+                names.push(' ');
+                argument.get_or_insert_with(|| text.trim().to_owned());
+            }
+            _ => names.push_str(text),
+        }
+    }
+    (names.split_whitespace().join(" "), argument)
+}
+
+/// Split an option of a plain text header, like `--output=<file>`, into its
+/// name and argument placeholder. `--color[=WHEN]` may be given without its
+/// argument.
+fn split_option(option: &str) -> Option<(&str, Option<String>)> {
+    let caps =
+        regex!(r"^([-+](?:-\[no-?\])?[^\s=<\[]+)(\s*\[?[\s=]*<?([^\s>\]]+))?").captures(option)?;
+    let argument = caps
+        .get(3)
+        .map(|m| match caps[2].trim_start().starts_with('[') {
+            true => format!("[{}]", m.as_str()),
+            false => m.as_str().to_owned(),
+        });
+    Some((caps.get(1).unwrap().as_str(), argument))
+}
+
+#[test]
+fn test_split_option_header() {
+    assert_eq!(
+        split_option_header(r"\fB-o\fR, \fB--output\fR=\fIFILE\fR"),
+        ("-o, --output=".to_owned(), Some("FILE".to_owned()))
+    );
+    assert_eq!(
+        split_option_header(r"\fB\-W\fIwarning\fR"),
+        ("-W".to_owned(), Some("warning".to_owned()))
+    );
+    assert_eq!(
+        split_option_header(r"\fB-v\fR \fI--verbose\fR"),
+        ("-v --verbose".to_owned(), None)
+    );
+    assert_eq!(
+        split_option_header("-x, --exclude PATTERN"),
+        ("-x, --exclude PATTERN".to_owned(), None)
+    );
+}
+
 #[test]
 fn test_remove_groff_formatting() {
     assert_eq!(
@@ -554,10 +1164,722 @@ fn test_remove_groff_formatting() {
     );
 }
 
+/// Merge the headers of `.TP` paragraphs that share a description into the
+/// first. Such headers are stacked with `.TQ`, or with `.TP`, often while
+/// `.PD 0` turns off the spacing between paragraphs: `.TP`, `-a`, `.PD 0`,
+/// `.TP`, `--all`, `.PD` becomes `.TP`, `-a, --all`.
+fn merge_stacked_headers(section: &str) -> Cow<'_, str> {
+    let mut lines = section.lines().peekable();
+    let mut merged = Vec::new();
+    let mut any_stacked = false;
+    while let Some(line) = lines.next() {
+        merged.push(Cow::Borrowed(line));
+        if !line.starts_with(".TP") {
+            continue;
+        }
+        let mut header = match lines.next() {
+            Some(header) => Cow::Borrowed(header),
+            None => break,
+        };
+        let mut stacked = false;
+        loop {
+            let mut ahead = lines.clone();
+            let mut next = ahead.next();
+            while next.is_some_and(|line| line.trim_end() == ".PD 0") {
+                next = ahead.next();
+            }
+            match (next, ahead.next()) {
+                (Some(next), Some(next_header))
+                    if next.starts_with(".TQ") || next.starts_with(".TP") =>
+                {
+                    header = Cow::Owned(format!("{}, {}", header, next_header));
+                    stacked = true;
+                    lines = ahead;
+                }
+                _ => break,
+            }
+        }
+        merged.push(header);
+        // The spacing is back to normal for the description
+        if stacked && lines.peek().is_some_and(|line| line.trim_end() == ".PD") {
+            lines.next();
+        }
+        any_stacked |= stacked;
+    }
+    if !any_stacked {
+        return Cow::Borrowed(section);
+    }
+    let mut merged = merged.join("\n");
+    if section.ends_with('\n') {
+        merged.push('\n');
+    }
+    Cow::Owned(merged)
+}
+
+#[test]
+fn test_merge_stacked_headers() {
+    assert_eq!(
+        merge_stacked_headers(
+            ".TP\n\\fB-a\\fR\n.PD 0\n.TP\n\\fB--all\\fR\n.PD\nShow all.\n\
+             .TP 8\n-b\n.TQ\n--brief\n.TQ\n--short\nBe brief.\n\
+             .TP\n-v\n.TP\n--verbose\nBe verbose.\n\
+             .PD 0\n.TP\n-c\nCount.\n.TP\n-d\nDebug.\n.PD"
+        ),
+        ".TP\n\\fB-a\\fR, \\fB--all\\fR\nShow all.\n\
+         .TP 8\n-b, --brief, --short\nBe brief.\n\
+         .TP\n-v, --verbose\nBe verbose.\n\
+         .PD 0\n.TP\n-c\nCount.\n.TP\n-d\nDebug.\n.PD"
+    );
+
+    assert_eq!(
+        merge_stacked_headers(".TP\n-a\n.TQ\n--all\nShow all.\n"),
+        ".TP\n-a, --all\nShow all.\n"
+    );
+    let section = ".TP\n-c\nCount.\n.TP\n-d\nDebug.\n";
+    assert!(matches!(merge_stacked_headers(section), Cow::Borrowed(s) if s == section));
+}
+
 trait ManParser {
     fn is_my_type(&self, manpage: &str) -> bool;
 
-    fn parse_man_page(&self, _manpage: &str, _cmdname: &str) -> Option<String>;
+    fn parse_man_page(&self, _manpage: &str) -> Option<Completions>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TypeHelp2man;
+
+impl ManParser for TypeHelp2man {
+    fn is_my_type(&self, manpage: &str) -> bool {
+        manpage.contains("generated by help2man")
+    }
+
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        // help2man turns the --help output into a `.TP` for each option, with
+        // the option on the next line and its description after that, in
+        // whichever sections --help had. Options it could not tell from their
+        // description get a `.HP`, with both on the next line.
+        let mut lines = manpage.lines().peekable();
+        let mut completions = Completions::new();
+        while let Some(line) = lines.next() {
+            let header_re = regex!(r"^(\\fB\S*?\\f[RP]\S*)\s*(?:\\-\s+)?(.*)");
+            let (header, description) = match line.trim_end() {
+                ".TP" => match lines.next() {
+                    Some(header) => (header, None),
+                    None => break,
+                },
+                ".HP" => match lines.next().and_then(|line| header_re.captures(line)) {
+                    Some(caps) => (
+                        caps.get(1).unwrap().as_str(),
+                        caps.get(2).map(|m| m.as_str()),
+                    ),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let header = remove_groff_formatting_except_fonts(header);
+            let description: Vec<_> = description
+                .into_iter()
+                .chain(lines.peeking_take_while(|line| {
+                    !line.starts_with(['.', '\''].as_ref()) || *line == ".br"
+                }))
+                .filter(|line| *line != ".br")
+                .collect();
+
+            let (option_name, argument) = match split_option_header(&header) {
+                // Plain text placeholders, like the `<char>` of `-O=<char>`,
+                // aren't in italics
+                (names, None) => {
+                    let last = names.split_whitespace().last().unwrap_or_default();
+                    match split_option(last) {
+                        Some((name, argument)) if last.contains('=') => (
+                            names[..names.len() - last.len()].to_owned() + name,
+                            argument,
+                        ),
+                        _ => (names, None),
+                    }
+                }
+                header => header,
+            };
+            // Not operators like test(1)'s `INTEGER1 -eq INTEGER2`
+            if !option_name.starts_with(['-', '+'].as_ref()) {
+                // add_diagnostic(format!("{:?} doesn't start with '-'", option_name));
+                continue;
+            }
+            // `--color[=WHEN]` may be given without its argument
+            let argument = match argument {
+                Some(argument) if option_name.contains('[') => Some(format!("[{}]", argument)),
+                argument => argument,
+            };
+            let description = remove_groff_formatting(&description.join(" "))
+                .trim()
+                .to_owned();
+            completions.add_with_argument(&option_name, argument.as_deref(), &description);
+        }
+        completions.build()
+    }
+}
+
+#[test]
+fn test_type_help2man() {
+    let manpage = r#".\" DO NOT MODIFY THIS FILE!  It was generated by help2man 1.48.5.
+.TH LS "1" "September 2022" "GNU coreutils 9.1" "User Commands"
+.SH DESCRIPTION
+.PP
+Mandatory arguments to long options are mandatory for short options too.
+.TP
+\fB\-a\fR, \fB\-\-all\fR
+do not ignore entries starting with .
+.TP
+\fB\-\-block\-size\fR=\fI\,SIZE\/\fR
+with \fB\-l\fR, scale sizes by SIZE when printing them;
+e.g., '\-\-block\-size=M'; see SIZE format below
+.TP
+\fB\-\-color\fR[=\fI\,WHEN\/\fR]
+color the output WHEN; more info below
+.TP
+\fB\-T\fR, \fB\-\-tabsize\fR=\fI\,COLS\/\fR
+assume tab stops at each COLS instead of 8
+.PP
+The SIZE argument is an integer.
+.TP
+none, off
+no quoting
+.TP
+INTEGER1 \fB\-eq\fR INTEGER2
+INTEGER1 is equal to INTEGER2
+.HP
+\fB\-O=\fR<char>      \- Optimization level.
+.HP
+\fB\-Bstatic\fR       \fB\-l\fR does not search for shared libraries
+"#;
+    assert_eq!(
+        TypeHelp2man.parse_man_page(manpage).map(|c| c.to_fish("ls")),
+        Some(
+            "complete -c ls -s a -l all --description 'do not ignore entries starting with .'\n\
+             complete -c ls -l block-size -r --description 'with -l, scale sizes by SIZE when printing them; e.g., \\'--block-size=M\\'; see …'\n\
+             complete -c ls -l color --description 'color the output WHEN; more info below'\n\
+             complete -c ls -s T -l tabsize -r --description 'assume tab stops at each COLS instead of 8'\n\
+             complete -c ls -s O -r --description 'Optimization level'\n\
+             complete -c ls -o Bstatic --description '-l does not search for shared libraries'"
+                .to_owned()
+        )
+    );
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TypePod2man;
+
+impl ManParser for TypePod2man {
+    fn is_my_type(&self, manpage: &str) -> bool {
+        manpage.contains("generated by Pod::Man")
+    }
+
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        // Without a section for them, the options are in the description
+        let in_description = !TypePod2man::has_options_section(manpage);
+        let mut in_options = false;
+        // Pod::Man sets each item as `.IP "header" 4` followed by an `.IX Item`
+        // index entry. Items under `.PD 0` share the description of the last.
+        let mut headers: Vec<(String, Option<String>)> = Vec::new();
+        let mut lines = manpage.lines().peekable();
+        let mut completions = Completions::new();
+        while let Some(line) = lines.next() {
+            if let Some(title) = line.strip_prefix(".SH ") {
+                in_options = TypePod2man::is_options_title(title)
+                    || (in_description && title.contains("DESCRIPTION"));
+                headers.clear();
+                continue;
+            }
+            let header = match line.strip_prefix(".IP ") {
+                Some(args) if in_options => deroff::Deroffer::macro_args(args),
+                _ => continue,
+            };
+            let description: Vec<_> = lines
+                .peeking_take_while(|line| {
+                    !line.starts_with('.') || line.starts_with(".IX") || line.starts_with(".PD")
+                })
+                .filter(|line| !line.starts_with('.'))
+                .collect();
+
+            let header = header.into_iter().next().unwrap_or_default();
+            let (option_name, argument) = split_option_header(&header);
+            if !option_name.starts_with(['-', '+'].as_ref()) {
+                // add_diagnostic(format!("{:?} doesn't start with '-'", option_name));
+                headers.clear();
+                continue;
+            }
+            headers.push((option_name, argument));
+            if description.is_empty() {
+                continue;
+            }
+            let option_name = headers.iter().map(|(name, _)| name).join(", ");
+            let argument = headers.drain(..).find_map(|(_, argument)| argument);
+            let description = TypePod2man::plain_text(&description.join(" "));
+            completions.add_with_argument(&option_name, argument.as_deref(), &description);
+        }
+        completions.build()
+    }
+}
+
+impl TypePod2man {
+    fn is_options_title(title: &str) -> bool {
+        ["OPTION", "COMMAND", "ACTION", "PARAMETER", "ARGUMENT"]
+            .iter()
+            .any(|word| title.contains(word))
+    }
+
+    fn has_options_section(manpage: &str) -> bool {
+        manpage
+            .lines()
+            .filter_map(|line| line.strip_prefix(".SH "))
+            .any(TypePod2man::is_options_title)
+    }
+
+    /// The text of a description, without the strings of the Pod::Man preamble
+    /// or size changes.
+    fn plain_text(description: &str) -> String {
+        let strings_re = regex!(r#"\\\*\((L"|R"|C\+|C`|C'|--|PI|Aq)|\\\(aq|\\[|&]|\\s[-+]?\d"#);
+        let description = strings_re.replace_all(description, |caps: &regex::Captures| match caps
+            .get(1)
+            .map(|m| m.as_str())
+        {
+            Some("L\"") | Some("R\"") => "\"",
+            Some("C+") => "C++",
+            Some("--") => "--",
+            Some("PI") => "pi",
+            Some("Aq") => "'",
+            Some(_) => "",
+            None if &caps[0] == "\\(aq" => "'",
+            None => "",
+        });
+        remove_groff_formatting(&description).trim().to_owned()
+    }
+}
+
+#[test]
+fn test_type_pod2man() {
+    let manpage = r#".\" Automatically generated by Pod::Man 4.14 (Pod::Simple 3.43)
+.de IX
+..
+.SH "NAME"
+openssl\-rand \- generate pseudo\-random bytes
+.SH "DESCRIPTION"
+.IX Header "DESCRIPTION"
+.IP "\fB\-not\fR" 4
+Not an option here.
+.SH "OPTIONS"
+.IX Header "OPTIONS"
+.IP "\fB\-help\fR" 4
+.IX Item "-help"
+Print out a usage message.
+.IP "\fB\-out\fR \fIfile\fR" 4
+.IX Item "-out file"
+Write to \fIfile\fR instead of standard output.
+.IP "\fB\-engine\fR \fIid\fR" 4
+.IX Item "-engine id"
+See \*(L"Engine Options\*(R" in \fBopenssl\fR\|(1).
+This option is \s-1DEPRECATED\s0.
+.IP "\fB\-provider\fR \fIname\fR" 4
+.IX Item "-provider name"
+.PD 0
+.IP "\fB\-provider\-path\fR \fIpath\fR" 4
+.IX Item "-provider-path path"
+.PD
+See \*(L"Provider Options\*(R" in \fBopenssl\fR\|(1).
+.IP "\fIfile\fR" 4
+.IX Item "file"
+Not an option either.
+.SH "SEE ALSO"
+"#;
+    assert_eq!(
+        TypePod2man.parse_man_page(manpage).map(|c| c.to_fish("openssl-rand")),
+        Some(
+            "complete -c openssl-rand -o help --description 'Print out a usage message'\n\
+             complete -c openssl-rand -o out -r -F --description 'Write to file instead of standard output'\n\
+             complete -c openssl-rand -o engine -r --description 'See \"Engine Options\" in openssl(1).  This option is DEPRECATED'\n\
+             complete -c openssl-rand -o provider -o provider-path -r --description 'See \"Provider Options\" in openssl(1)'"
+                .to_owned()
+        )
+    );
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TypeMarkdown;
+
+impl ManParser for TypeMarkdown {
+    fn is_my_type(&self, manpage: &str) -> bool {
+        // go-md2man leaves no comment, but always starts with these
+        manpage.contains("generated with Ronn")
+            || manpage.contains("generated by Pandoc")
+            || regex!(r"(?m)^\.TH .*\n\.nh\n\.ad l$").is_match(manpage)
+    }
+
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        // Markdown converters set a list item as a paragraph with the option
+        // on its first line: go-md2man indents the description on the lines
+        // after it, pandoc and ronn put it after a `.TP` header, and ronn-ng
+        // after a bare `.IP`, with a colon ending the header.
+        let mut lines = manpage.lines().peekable();
+        let mut completions = Completions::new();
+        while let Some(line) = lines.next() {
+            match line.split_whitespace().next() {
+                Some(".PP" | ".P" | ".LP" | ".TP" | ".IP") => (),
+                _ => continue,
+            }
+            let header = match lines.peek() {
+                Some(header) if !header.starts_with('.') => lines.next().unwrap(),
+                Some(header) => match header.strip_prefix(".B ") {
+                    Some(header) => {
+                        lines.next();
+                        header
+                    }
+                    None => continue,
+                },
+                None => break,
+            };
+            while lines
+                .peek()
+                .is_some_and(|line| *line == ".IP" || line.starts_with(".RS"))
+            {
+                lines.next();
+            }
+            let description: Vec<_> = lines
+                .peeking_take_while(|line| !line.starts_with(['.', '\''].as_ref()))
+                .collect();
+
+            let header = header.trim().trim_end_matches(':');
+            let (option_name, argument) = match split_option_header(header) {
+                // ronn sets the argument in bold with the option, `--indent n`
+                (names, None) => match names.rsplit_once(' ') {
+                    Some((name, word))
+                        if name.rsplit(' ').next().is_some_and(|n| n.starts_with('-'))
+                            && regex!(r"^(?:[[:lower:]]+|[[:upper:]]+)$").is_match(word) =>
+                    {
+                        (name.to_owned(), Some(word.to_owned()))
+                    }
+                    _ => (names, None),
+                },
+                header => header,
+            };
+            // ronn-ng pages may list the names as `--slurp/-s`
+            let option_name = option_name.replace("/-", ", -");
+            if !option_name.starts_with('-') {
+                // add_diagnostic(format!("{:?} doesn't start with '-'", option_name));
+                continue;
+            }
+            // go-md2man shows the default instead, `--force[=false]` for a flag
+            let argument = argument.or_else(|| {
+                regex!(r"[^\[]=")
+                    .is_match(&option_name)
+                    .then(|| "VALUE".to_owned())
+            });
+            let description = metadata::plain_text(&description.join("\n"));
+            completions.add_with_argument(&option_name, argument.as_deref(), &description);
+        }
+        completions.build()
+    }
+}
+
+#[test]
+fn test_type_markdown() {
+    let manpage = std::fs::read_to_string("fixtures/docker-rmi.1").unwrap();
+    assert!(TypeMarkdown.is_my_type(&manpage));
+    assert_eq!(
+        TypeMarkdown
+            .parse_man_page(&manpage)
+            .map(|c| c.to_fish("docker-rmi")),
+        Some(
+            "complete -c docker-rmi -s f -l force --description 'Force removal of the image'\n\
+             complete -c docker-rmi -s h -l help --description 'help for rmi'\n\
+             complete -c docker-rmi -l no-prune --description 'Do not delete untagged parents'"
+                .to_owned()
+        )
+    );
+
+    let manpage = r#".\" generated with Ronn-NG/v0.9.1
+.TH "JQ" "1" "July 2025" ""
+.SH "INVOKING JQ"
+You can affect how jq reads and writes its input and output using some command\-line options:
+.IP "\[ci]" 4
+\fB\-\-version\fR:
+.IP
+Output the jq version and exit with zero\.
+.IP "\[ci]" 4
+\fB\-\-indent n\fR:
+.IP
+Use the given number of spaces (no more than 7) for indentation\.
+.IP "\[ci]" 4
+\fB\-\-slurp\fR/\fB\-s\fR:
+.IP
+Read the entire input stream into a large array\.
+.SH "SEE ALSO"
+.TP
+\fBjq\-filters\fR(7)
+"#;
+    assert_eq!(
+        TypeMarkdown.parse_man_page(manpage).map(|c| c.to_fish("jq")),
+        Some(
+            "complete -c jq -l version --description 'Output the jq version and exit with zero'\n\
+             complete -c jq -l indent -r --description 'Use the given number of spaces (no more than 7) for indentation'\n\
+             complete -c jq -l slurp -s s --description 'Read the entire input stream into a large array'"
+                .to_owned()
+        )
+    );
+
+    let manpage = r#".\" Automatically generated by Pandoc 3.1.3
+.TH "PANDOC" "1" "" "pandoc 3.1.3" ""
+.SH OPTIONS
+.TP
+\f[B]\-o\f[R] \f[I]FILE\f[R], \f[B]\-\-output=\f[R]\f[I]FILE\f[R]
+Write output to \f[I]FILE\f[R] instead of \f[I]stdout\f[R].
+.TP
+.B \-\-verbose
+Give verbose debugging output.
+"#;
+    assert_eq!(
+        TypeMarkdown.parse_man_page(manpage).map(|c| c.to_fish("pandoc")),
+        Some(
+            "complete -c pandoc -s o -l output -r -F --description 'Write output to FILE instead of stdout'\n\
+             complete -c pandoc -l verbose --description 'Give verbose debugging output'"
+                .to_owned()
+        )
+    );
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TypeRst;
+
+impl ManParser for TypeRst {
+    fn is_my_type(&self, manpage: &str) -> bool {
+        manpage.contains("Man page generated from reStructuredText")
+    }
+
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        // docutils sets option lists and Sphinx `option` directives as a `.TP`
+        // with the header in `.B` or `.BI`, and definitions quoted in their own
+        // `.INDENT` after a `.sp` paragraph with the header
+        let mut lines = manpage.lines().peekable();
+        let mut completions = Completions::new();
+        while let Some(line) = lines.next() {
+            let mut ahead = lines.clone();
+            let header = match (line.trim_end(), ahead.next(), ahead.next()) {
+                (".TP", Some(header), _) => TypeRst::header_text(header),
+                (".sp", Some(header), Some(indent))
+                    if header.starts_with("\\fB\\-") && indent.starts_with(".INDENT") =>
+                {
+                    TypeRst::header_text(header)
+                }
+                _ => continue,
+            };
+            let header = match header {
+                Some(header) => header,
+                None => continue,
+            };
+            lines.next();
+            while lines.peek().is_some_and(|line| line.starts_with(".INDENT")) {
+                lines.next();
+            }
+            let description: Vec<_> = lines
+                .peeking_take_while(|line| !line.starts_with(['.', '\''].as_ref()))
+                .collect();
+
+            let options: Vec<_> = regex!(r",\s+")
+                .split(&header)
+                .filter_map(split_option)
+                .collect();
+            if options.is_empty() {
+                // add_diagnostic(format!("{:?} has no option", header));
+                continue;
+            }
+            let option_name = options.iter().map(|(name, _)| name).join(", ");
+            let argument = options.into_iter().find_map(|(_, argument)| argument);
+            let description = metadata::plain_text(&description.join("\n"));
+            completions.add_with_argument(&option_name, argument.as_deref(), &description);
+        }
+        completions.build()
+    }
+}
+
+impl TypeRst {
+    /// The text of a header line, which may be a `.B` or `.BI` request.
+    fn header_text(line: &str) -> Option<String> {
+        let header = match line.split_once(' ') {
+            Some((".B", args)) => deroff::Deroffer::macro_args(args).join(" "),
+            Some((".BI" | ".BR" | ".IB", args)) => deroff::Deroffer::macro_args(args).concat(),
+            _ if line.starts_with('.') => return None,
+            _ => line.to_owned(),
+        };
+        Some(metadata::plain_text(&header))
+    }
+}
+
+#[test]
+fn test_type_rst() {
+    let manpage = r#".\" Man page generated from reStructuredText.
+.TH "PIP" "1" "" "" ""
+.SH COMMANDS
+.INDENT 0.0
+.TP
+.B help
+Show help for commands.
+.UNINDENT
+.SH OPTIONS
+.INDENT 0.0
+.TP
+.BI \-r\fP,\fB  \-\-requirement \ <file>
+Install from the given requirements file.  This option can be used multiple
+times.
+.TP
+.B \-a, \-\-all
+Show all symbols, even those usually
+suppressed.
+.TP
+.B \-\-format=<format>, \-f
+Select an output format; \fIformat\fP may be \fIsysv\fP or \fIbsd\fP\&.
+.TP
+.B \-\-color[=WHEN]
+Color the output.
+.UNINDENT
+.sp
+\fB\-\-stream\fP
+.INDENT 0.0
+.INDENT 3.5
+Generate pixbuf data stream
+.UNINDENT
+.UNINDENT
+.sp
+\fB\-\-stream\fP is the default.
+"#;
+    assert_eq!(
+        TypeRst.parse_man_page(manpage).map(|c| c.to_fish("pip")),
+        Some(
+            "complete -c pip -s r -l requirement -r -F --description 'Install from the given requirements file'\n\
+             complete -c pip -s a -l all --description 'Show all symbols, even those usually suppressed'\n\
+             complete -c pip -l format -s f -r --description 'Select an output format; format may be sysv or bsd'\n\
+             complete -c pip -l color --description 'Color the output'\n\
+             complete -c pip -l stream --description 'Generate pixbuf data stream'"
+                .to_owned()
+        )
+    );
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TypeDocbook;
+
+impl ManParser for TypeDocbook {
+    fn is_my_type(&self, manpage: &str) -> bool {
+        manpage.contains("Generator: DocBook XSL") || manpage.contains("Generator: Asciidoctor")
+    }
+
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        // Each entry of a variable list is a paragraph with its terms, split
+        // by `.br`, followed by the definition in an `.RS 4` block. Further
+        // paragraphs, examples and nested lists of the definition are in
+        // blocks of their own, so only the first paragraph is used.
+        let mut lines = manpage.lines().peekable();
+        let mut completions = Completions::new();
+        while let Some(line) = lines.next() {
+            if !matches!(line.trim_end(), ".PP" | ".sp") {
+                continue;
+            }
+            let terms: Vec<_> = lines
+                .peeking_take_while(|line| {
+                    !line.starts_with(['.', '\''].as_ref()) || *line == ".br"
+                })
+                .filter(|line| *line != ".br")
+                .collect();
+            if terms.is_empty() || !lines.peek().is_some_and(|line| line.starts_with(".RS")) {
+                continue;
+            }
+            lines.next();
+            while lines.peek().is_some_and(|line| *line == ".sp") {
+                lines.next();
+            }
+            let description: Vec<_> = lines
+                .peeking_take_while(|line| !line.starts_with(['.', '\''].as_ref()))
+                .collect();
+
+            let mut names = Vec::new();
+            let mut argument = None;
+            for term in terms {
+                let (term, placeholder) = split_option_header(unquote_double_quotes(term.trim()));
+                argument = argument.or(placeholder);
+                for (name, placeholder) in regex!(r",\s*").split(&term).filter_map(split_option) {
+                    names.push(name.to_owned());
+                    argument = argument.or(placeholder);
+                }
+            }
+            if names.is_empty() {
+                // add_diagnostic(format!("{:?} has no option", terms));
+                continue;
+            }
+            let description = metadata::plain_text(&description.join("\n"));
+            completions.add_with_argument(&names.join(", "), argument.as_deref(), &description);
+        }
+        completions.build()
+    }
+}
+
+#[test]
+fn test_type_docbook() {
+    let manpage = r#".\" Generator: DocBook XSL Stylesheets vsnapshot <http://docbook.sf.net/>
+.SH "OPTIONS"
+.PP
+createdb
+accepts the following command\-line arguments:
+.PP
+\fIdbname\fR
+.RS 4
+Specifies the name of the database to be created\&.
+.RE
+.PP
+\fB\-D \fR\fB\fItablespace\fR\fR
+.br
+\fB\-\-tablespace=\fR\fB\fItablespace\fR\fR
+.RS 4
+Specifies the default tablespace for the database\&.
+.RE
+.PP
+\-C <commit>, \-\-reuse\-message=<commit>
+.RS 4
+Take an existing commit object\&.
+.RE
+.PP
+\-p, \-\-patch
+.RS 4
+.sp
+Use the interactive patch selection
+interface\&.
+.sp
+.RS 4
+.ie n \{\
+\h'-04'\(bu\h'+03'\c
+.\}
+Nested list
+.RE
+.PP
+\-\-nested
+.RS 4
+A nested option\&.
+.RE
+.RE
+.PP
+"\fB\-\-file\fR \fIfile\fR"
+.RS 4
+Read from \fIfile\fR\&.
+.RE
+"#;
+    assert_eq!(
+        TypeDocbook.parse_man_page(manpage).map(|c| c.to_fish("createdb")),
+        Some(
+            "complete -c createdb -s D -l tablespace -r --description 'Specifies the default tablespace for the database'\n\
+             complete -c createdb -s C -l reuse-message -r --description 'Take an existing commit object'\n\
+             complete -c createdb -s p -l patch --description 'Use the interactive patch selection interface'\n\
+             complete -c createdb -l nested --description 'A nested option'\n\
+             complete -c createdb -l file -r -F --description 'Read from file'"
+                .to_owned()
+        )
+    );
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -568,7 +1890,7 @@ impl ManParser for Type1 {
         manpage.contains(r#".SH "OPTIONS""#)
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let options_section_re = regex!(r#"\.SH "OPTIONS"((?s:.)*?)(\.SH|\z)"#);
         let options_section_matched = options_section_re.find(manpage);
         let mut options_section = options_section_matched.unwrap().as_str();
@@ -580,11 +1902,11 @@ impl ManParser for Type1 {
         if options_matched.is_none() {
             // add_diagnostic("Unable to find options");
             return self
-                .fallback(options_section, cmdname)
-                .or_else(|| self.fallback2(options_section, cmdname));
+                .fallback(options_section)
+                .or_else(|| self.fallback2(options_section));
         }
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let mut data = mat.get(1).unwrap().as_str();
             let last_dotpp_index = data.rfind(".PP");
@@ -592,17 +1914,17 @@ impl ManParser for Type1 {
                 data = &data[idx + 3..];
             }
 
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             if let Some((option_name, option_desc)) = data.splitn(2, ".RS 4").next_tuple::<(_, _)>()
             {
                 let option_name = option_name.trim();
-                if option_name.contains('-') {
-                    let option_name = unquote_double_quotes(option_name);
-                    let option_name = unquote_single_quotes(option_name);
-                    let option_desc = option_desc.trim().replace('\n', " ");
-                    completions.add(option_name, &option_desc);
+                let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+                let (option_name, _) = split_option_header(option_name);
+                if option_name.contains(['-', '+']) {
+                    let option_desc = remove_fonts(option_desc).trim().replace('\n', " ");
+                    completions.add(&option_name, &option_desc);
                 } else {
-                    // add_diagnostic(format!("{:?} doesn't contain '-'", option_name));
+                    // add_diagnostic(format!("{:?} doesn't contain '-' or '+'", option_name));
                 }
             } else {
                 // add_diagnostic("Unable to split option from description");
@@ -617,29 +1939,31 @@ impl ManParser for Type1 {
 }
 
 impl Type1 {
-    fn fallback(&self, mut options_section: &str, cmdname: &str) -> Option<String> {
+    fn fallback(&self, options_section: &str) -> Option<Completions> {
         // add_diagnostic("Trying fallback");
+        let options_section = merge_stacked_headers(options_section);
+        let mut options_section = &*options_section;
         let options_parts_re = regex!(r"\.TP( \d+)?((?s:.)*?)\.TP");
         let mut options_matched = options_parts_re.captures(options_section);
         if options_matched.is_none() {
             // add_diagnostic("Still not found");
             return None;
         }
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(2).unwrap().as_str();
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             let data = data.splitn(2, '\n').next_tuple::<(_, _)>();
             if data.filter(|data| !data.1.trim().is_empty()).is_none() {
                 // add_diagnostic("Unable to split option from description");
                 return None;
             }
             let option_name = data.unwrap().0.trim();
-            if option_name.contains('-') {
-                let option_name = unquote_double_quotes(option_name);
-                let option_name = unquote_single_quotes(option_name);
-                let option_desc = data.unwrap().1.trim().replace('\n', " ");
-                completions.add(option_name, &option_desc);
+            let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+            let (option_name, _) = split_option_header(option_name);
+            if option_name.contains(['-', '+']) {
+                let option_desc = remove_fonts(data.unwrap().1).trim().replace('\n', " ");
+                completions.add(&option_name, &option_desc);
             } else {
                 // add_diagnostic(format!("{:?} does not contain '-'", option_name));
             }
@@ -651,7 +1975,7 @@ impl Type1 {
         completions.build()
     }
 
-    fn fallback2(&self, options_section: &str, cmdname: &str) -> Option<String> {
+    fn fallback2(&self, options_section: &str) -> Option<Completions> {
         // add_diagnostic("Trying last chance fallback");
         let ix_remover_re = regex!(r"\.IX.*");
         let trailing_num_re = regex!(r"\d+$");
@@ -663,24 +1987,24 @@ impl Type1 {
             // add_diagnostic("Still (still!) not found");
             return None;
         }
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(1).unwrap().as_str();
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             let data: Vec<&str> = data.splitn(2, '\n').collect();
             if data.len() < 2 || data[1].trim().is_empty() {
                 // add_diagnostic("Unable to split option from description");
                 return None;
             }
             let option_name = trailing_num_re.replace_all(data[0].trim(), "");
-            if option_name.contains('-') {
-                let option_name = option_name.trim();
-                let option_name = unquote_double_quotes(option_name);
-                let option_name = unquote_single_quotes(option_name);
-                let option_desc = data[1].trim().replace('\n', " ");
-                completions.add(option_name, &option_desc);
+            let option_name = option_name.trim();
+            let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+            let (option_name, _) = split_option_header(option_name);
+            if option_name.contains(['-', '+']) {
+                let option_desc = remove_fonts(data[1]).trim().replace('\n', " ");
+                completions.add(&option_name, &option_desc);
             } else {
-                // add_diagnostic(format!("{:?} doesn't contain '-'", option_name));
+                // add_diagnostic(format!("{:?} doesn't contain '-' or '+'", option_name));
             }
 
             options_section = &options_section[mat.get(0).unwrap().end() - 3..];
@@ -698,10 +2022,12 @@ impl ManParser for Type2 {
         manpage.contains(".SH OPTIONS")
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let options_section_re = regex!(r#"\.SH OPTIONS((?s:.)*?)(\.SH|\z)"#);
         let options_section_matched = options_section_re.captures(manpage);
-        let mut options_section = options_section_matched.unwrap().get(1).unwrap().as_str();
+        let options_section = options_section_matched.unwrap().get(1).unwrap().as_str();
+        let options_section = merge_stacked_headers(options_section);
+        let mut options_section = &*options_section;
 
         let options_parts_re = regex!(r#"\.[IT]P( \d+(\.\d)?i?)?((?s:.)*?)\.([IT]P|UNINDENT)"#);
         let mut options_matched = options_parts_re.captures(options_section);
@@ -712,22 +2038,22 @@ impl ManParser for Type2 {
             return None;
         }
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(3).unwrap().as_str();
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             let data = data.trim().splitn(2, '\n').next_tuple::<(_, _)>();
             if let Some((option_name, option_desc)) =
                 data.filter(|(_, desc)| !desc.trim().is_empty())
             {
                 let option_name = option_name.trim();
-                if option_name.contains('-') {
-                    let option_name = unquote_double_quotes(option_name);
-                    let option_name = unquote_single_quotes(option_name);
-                    let option_desc = option_desc.trim().replace('\n', " ");
-                    completions.add(option_name, &option_desc);
+                let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+                let (option_name, _) = split_option_header(option_name);
+                if option_name.contains(['-', '+']) {
+                    let option_desc = remove_fonts(option_desc).trim().replace('\n', " ");
+                    completions.add(&option_name, &option_desc);
                 } else {
-                    // add_diagnostic(format!("{:?} doesn't contain '-'", option_name));
+                    // add_diagnostic(format!("{:?} doesn't contain '-' or '+'", option_name));
                 }
             } else {
                 // add_diagnostic("Unable to split option from description");
@@ -741,6 +2067,37 @@ impl ManParser for Type2 {
     }
 }
 
+#[test]
+fn test_type2_stacked_headers() {
+    let manpage = r#".SH OPTIONS
+.TP
+\fB\-a\fR
+.PD 0
+.TP
+\fB\-\-all\fR
+.PD
+Show all entries.
+.TP
+\fB\-b\fR
+.TQ
+\fB\-\-brief\fR
+Be brief.
+.TP
+\fB\-\-version\fR
+Print the version.
+.SH SEE ALSO
+"#;
+    // Type2 leaves out the last paragraph, which nothing after it ends
+    assert_eq!(
+        Type2.parse_man_page(manpage).map(|c| c.to_fish("ls")),
+        Some(
+            "complete -c ls -s a -l all --description 'Show all entries'\n\
+             complete -c ls -s b -l brief --description 'Be brief'"
+                .to_owned()
+        )
+    );
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 struct Type3;
 
@@ -749,10 +2106,11 @@ impl ManParser for Type3 {
         manpage.contains(".SH DESCRIPTION")
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let options_section_re = regex!(r"\.SH DESCRIPTION((?s:.)*?)(\.SH|\z)");
         let options_section_matched = options_section_re.find(manpage);
-        let mut options_section = options_section_matched.unwrap().as_str();
+        let options_section = merge_stacked_headers(options_section_matched.unwrap().as_str());
+        let mut options_section = &*options_section;
 
         let options_parts_re = regex!(r"\.TP((?s:.)*?)\.TP");
         let mut options_matched = options_parts_re.captures(options_section);
@@ -763,11 +2121,11 @@ impl ManParser for Type3 {
             return None;
         }
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(1).unwrap().as_str();
 
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             let data = data.trim();
             let (option_name, option_desc) = match data.splitn(2, '\n').next_tuple() {
                 Some(tuple) => tuple,
@@ -777,13 +2135,13 @@ impl ManParser for Type3 {
                 }
             };
             let option_name = option_name.trim();
-            if option_name.contains('-') {
-                let option_name = unquote_double_quotes(option_name);
-                let option_name = unquote_single_quotes(option_name);
-                let option_desc = option_desc.trim().replace("\n", " ");
+            let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+            let (option_name, _) = split_option_header(option_name);
+            if option_name.contains(['-', '+']) {
+                let option_desc = remove_fonts(option_desc).trim().replace('\n', " ");
                 completions.add(&option_name, &option_desc);
             } else {
-                // add_diagnostic(format!("{:?} doesn't contain '-'", option_name));
+                // add_diagnostic(format!("{:?} doesn't contain '-' or '+'", option_name));
             }
 
             options_section = &options_section[mat.get(0).unwrap().end() - 3..];
@@ -801,10 +2159,12 @@ impl ManParser for Type4 {
         manpage.contains(".SH FUNCTION LETTERS")
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let options_section_re = regex!(r"\.SH FUNCTION LETTERS((?s:.)*?)(\.SH|\z)");
         let options_section_matched = options_section_re.captures(manpage);
-        let mut options_section = options_section_matched.unwrap().get(1).unwrap().as_str();
+        let options_section = options_section_matched.unwrap().get(1).unwrap().as_str();
+        let options_section = merge_stacked_headers(options_section);
+        let mut options_section = &*options_section;
 
         let options_parts_re = regex!(r"\.TP((?s:.)*?)\.TP");
         let mut options_matched = options_parts_re.captures(options_section);
@@ -815,19 +2175,19 @@ impl ManParser for Type4 {
             return None;
         }
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(1).unwrap().as_str();
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
             if let Some((option_name, option_desc)) = data.trim().splitn(2, '\n').next_tuple() {
                 let option_name = option_name.trim();
-                if option_name.contains('-') {
-                    let option_name = unquote_double_quotes(option_name);
-                    let option_name = unquote_single_quotes(option_name);
-                    let option_desc = option_desc.trim().replace('\n', " ");
-                    completions.add(option_name, &option_desc);
+                let option_name = unquote_single_quotes(unquote_double_quotes(option_name));
+                let (option_name, _) = split_option_header(option_name);
+                if option_name.contains(['-', '+']) {
+                    let option_desc = remove_fonts(option_desc).trim().replace('\n', " ");
+                    completions.add(&option_name, &option_desc);
                 } else {
-                    // add_diagnostic(format!("{} doesn't contain '-'", option_name));
+                    // add_diagnostic(format!("{} doesn't contain '-' or '+'", option_name));
                 }
             } else {
                 // add_diagnostic("Unable to split option from description");
@@ -849,7 +2209,7 @@ impl ManParser for TypeScdoc {
         regex!(r#"\.\\" Generated by scdoc(?s:.)?*\.SH OPTIONS"#).is_match(manpage)
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let options_section_re = regex!(r"\.SH OPTIONS((?s:.)*?)\.SH");
         let options_section_matched = options_section_re.captures(manpage);
         let mut options_section = options_section_matched.unwrap().get(1)?.as_str();
@@ -863,10 +2223,10 @@ impl ManParser for TypeScdoc {
             return None;
         }
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(mat) = options_matched {
             let data = mat.get(1).unwrap().as_str();
-            let data = remove_groff_formatting(data);
+            let data = remove_groff_formatting_except_fonts(data);
 
             // Should be at least two lines, split name and desc, other lines ignored
             let lines = data.split('\n');
@@ -874,10 +2234,11 @@ impl ManParser for TypeScdoc {
             if let Some((option_name, option_desc)) = iter.next_tuple() {
                 let option_name = unquote_double_quotes(option_name);
                 let option_name = unquote_single_quotes(option_name);
-                if !option_name.contains('-') {
-                    // add_diagnostic(format!("{} doesn't contain '-'", option_name));
+                let (option_name, _) = split_option_header(option_name);
+                if !option_name.contains(['-', '+']) {
+                    // add_diagnostic(format!("{} doesn't contain '-' or '+'", option_name));
                 }
-                completions.add(option_name, option_desc);
+                completions.add(&option_name, &remove_fonts(option_desc));
             } else {
                 // add_diagnostic(format!("Unable to split option from description"));
             }
@@ -897,12 +2258,12 @@ impl ManParser for TypeDarwin {
         regex!(r##"\.S[hH] DESCRIPTION"##).is_match(manpage)
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
         let mut lines = manpage.split_terminator("\n").skip_while(|cond| {
             !cond.starts_with(".Sh DESCRIPTION") || !cond.starts_with(".SH DESCRIPTION")
         });
 
-        let mut completions = Completions::new(cmdname);
+        let mut completions = Completions::new();
         while let Some(line) = lines.next() {
             if !Self::is_option(line) {
                 continue;
@@ -1071,42 +2432,124 @@ impl ManParser for TypeDeroff {
         true // We're optimists
     }
 
-    fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
-        let mut deroffer = deroff::Deroffer::new();
-        deroffer.deroff(manpage.to_owned());
+    fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
+        self.parse_with_options(manpage, deroff::DeroffOptions::default())
+    }
+}
+
+impl TypeDeroff {
+    /// Parse the man page deroffed with the given options, as with
+    /// `--deroff-only`. Font annotations are always on, they tell option
+    /// names from their arguments. The options are in the sections from
+    /// DESCRIPTION or OPTIONS to BUGS, or anywhere if headers are skipped.
+    fn parse_with_options(
+        &self,
+        manpage: &str,
+        options: deroff::DeroffOptions,
+    ) -> Option<Completions> {
+        let skip_headers = options.skips_headers();
+        let options = options.font_annotations(true);
+        let mut deroffer = deroff::Deroffer::with_options(options);
+        deroffer.deroff(manpage).ok()?;
         let output = deroffer.get_output();
-        let lines = output.lines();
+        // Pair each line with its spans, the plain text is what we match on
+        let lines = output.lines().map(|line| {
+            let spans = deroff::font_spans(line);
+            let plain: String = spans.iter().map(|(_, text)| text.as_str()).collect();
+            (plain, spans)
+        });
 
         let mut lines = lines
             // Discard lines until we get to DESCRIPTION or OPTIONS
-            .skip_while(|line| {
-                !(line.starts_with("DESCRIPTION")
+            .skip_while(|(line, _)| {
+                !(skip_headers
+                    || line.starts_with("DESCRIPTION")
                     || line.starts_with("OPTIONS")
                     || line.starts_with("COMMAND OPTIONS"))
             })
             // Look for BUGS and stop there
-            .take_while(|line| !line.starts_with("BUGS"))
+            .take_while(|(line, _)| skip_headers || !line.starts_with("BUGS"))
             .peekable();
 
-        let mut completions = Completions::new(cmdname);
-        while let Some(options) = lines.next() {
+        let mut completions = Completions::new();
+        while let Some((options, spans)) = lines.next() {
             // Skip until we get to the next option
-            if !TypeDeroff::is_option(options) {
+            if !TypeDeroff::is_option(&options) {
+                continue;
+            }
+
+            // A tbl row: the option is in the first cell, its description in
+            // the next, with continuations in rows whose first cell is empty
+            if let Some((_, description)) = options.split_once('\t') {
+                let description: Vec<_> = std::iter::once(description.to_owned())
+                    .chain(
+                        lines
+                            .peeking_take_while(|(line, _)| line.starts_with('\t'))
+                            .map(|(line, _)| line),
+                    )
+                    .collect();
+                let description: Vec<_> = description.iter().map(|cell| cell.trim()).collect();
+                let header = TypeDeroff::first_cell(&spans);
+                let (options, _) = option_header_from_spans(&header);
+                completions.add(&options, &description.join(" "));
                 continue;
             }
 
             // Pop until we get to either an empty line or a line starting with -
             let description: Vec<_> = lines
-                .peeking_take_while(|line| TypeDeroff::could_be_description(line))
+                .peeking_take_while(|(line, _)| TypeDeroff::could_be_description(line))
+                .map(|(line, _)| line)
                 .collect();
             let description = description.join(" ");
 
+            let (options, _) = option_header_from_spans(&spans);
             completions.add(&options, &description);
         }
         completions.build()
     }
 }
 
+#[test]
+fn test_type_deroff_skip_headers() {
+    // Without titles, options are found in any section
+    let skip_headers = deroff::DeroffOptions::new().skip_headers(true);
+    let manpage = ".SH NAME\nfoo \\- do things\n.SH SWITCHES\n.TP\n\\fB\\-v\\fR\nBe verbose.\n";
+    assert!(TypeDeroff.parse_man_page(manpage).is_none());
+    assert_eq!(
+        TypeDeroff
+            .parse_with_options(manpage, skip_headers)
+            .map(|c| c.to_fish("foo")),
+        Some("complete -c foo -s v --description 'Be verbose'".to_owned())
+    );
+
+    let opts = Opts::from_iter_safe(["fish-manpage-completions", "-z", "--skip-headers", "foo.1"]);
+    assert!(opts.unwrap().skip_headers);
+}
+
+#[test]
+fn test_type_deroff_tbl() {
+    let manpage = r#".SH OPTIONS
+.TS
+tab(@);
+l l.
+\-f, \-\-force@T{
+Do not prompt
+before removing
+T}
+\-v@Be verbose
+@about it
+.TE
+"#;
+    assert_eq!(
+        TypeDeroff.parse_man_page(manpage).map(|c| c.to_fish("rm")),
+        Some(
+            "complete -c rm -s f -l force --description 'Do not prompt before removing'\n\
+             complete -c rm -s v --description 'Be verbose about it'"
+                .to_owned()
+        )
+    );
+}
+
 #[test]
 fn test_TypeDeroff_is_option() {
     assert!(!TypeDeroff::is_option("Not an Option"));
@@ -1120,6 +2563,47 @@ impl TypeDeroff {
     }
 }
 
+#[test]
+fn test_type_deroff_fonts() {
+    let manpage = r#".SH OPTIONS
+.TP
+\fB\-W\fIwarning\fR
+Enable the warning
+.TP
+\fB\-\-output\fR=\fIfile\fR
+Write to file
+"#;
+    assert_eq!(
+        TypeDeroff.parse_man_page(manpage).map(|c| c.to_fish("cc")),
+        Some(
+            "complete -c cc -s W --description 'Enable the warning'\n\
+             complete -c cc -l output --description 'Write to file'"
+                .to_owned()
+        )
+    );
+}
+
+#[test]
+fn test_type_deroff_mdoc() {
+    let manpage = r#".Sh DESCRIPTION
+.Bl -tag -width Ds
+.It Fl a
+Include entries starting with a dot.
+.It Fl o Ar file , Fl Fl output Ns = Ns Ar file
+Write to
+.Pa file .
+.El
+"#;
+    assert_eq!(
+        TypeDeroff.parse_man_page(manpage).map(|c| c.to_fish("ls")),
+        Some(
+            "complete -c ls -s a --description 'Include entries starting with a dot'\n\
+             complete -c ls -s o -l output --description 'Write to file'"
+                .to_owned()
+        )
+    );
+}
+
 #[test]
 fn test_could_be_description() {
     assert!(TypeDeroff::could_be_description("Test Pass Line"));
@@ -1131,6 +2615,19 @@ impl TypeDeroff {
     fn could_be_description(line: &str) -> bool {
         line.len() > 0 && !line.starts_with("-")
     }
+
+    /// The spans up to the first tab, the first cell of a tbl row.
+    fn first_cell(spans: &[(deroff::Font, String)]) -> Vec<(deroff::Font, String)> {
+        let mut cell = Vec::new();
+        for (font, text) in spans {
+            if let Some((text, _)) = text.split_once('\t') {
+                cell.push((*font, text.to_owned()));
+                break;
+            }
+            cell.push((*font, text.clone()));
+        }
+        cell
+    }
 }
 
 #[test]
@@ -1300,10 +2797,39 @@ fn test_cleanup_autogenerated_file() {
     tests::remove_test_file(bad_path);
 }
 
+/// The file name of a man page without its section and compression
+/// extensions, e.g. `mkfs.ext4` for `mkfs.ext4.8.gz`.
+fn manpage_stem(manpage_path: &Path) -> String {
+    let file_name = manpage_path.file_name().unwrap_or_default();
+    let file_name = file_name.to_string_lossy();
+    let stem = regex!(r"(\.(\d\w*|n))?(\.(gz|bz2|xz|lzma))?$").replace(&file_name, "");
+    stem.into_owned()
+}
+
+#[test]
+fn test_manpage_stem() {
+    let stem = |path: &str| manpage_stem(Path::new(path));
+    assert_eq!(stem("/usr/share/man/man1/ls.1.gz"), "ls");
+    assert_eq!(stem("mkfs.ext4.8.gz"), "mkfs.ext4");
+    assert_eq!(stem("python3.11.1"), "python3.11");
+    assert_eq!(
+        stem("systemd-resolved.service.8.xz"),
+        "systemd-resolved.service"
+    );
+    assert_eq!(stem("Tcl.n"), "Tcl");
+    assert_eq!(stem("git-config.1p.bz2"), "git-config");
+    assert_eq!(stem("docker-rmi"), "docker-rmi");
+}
+
+/// Parse the man page and write its completions. With `deroff_only`, only
+/// `TypeDeroff` is tried, with those options. The commands the page is for
+/// go into `description_index` along with its description.
 fn parse_manpage_at_path(
     manpage_path: &Path,
     output_directory: Option<&Path>,
-    deroff_only: bool,
+    deroff_only: Option<&deroff::DeroffOptions>,
+    description_index: Option<&mut metadata::DescriptionIndex>,
+    placeholders: &placeholders::Placeholders,
 ) -> io::Result<bool> {
     // Clear diagnostic
     // diagnostic_output[:] = []
@@ -1313,38 +2839,60 @@ fn parse_manpage_at_path(
     // add_diagnostic(format!("Considering {}", manpage_path));
     // diagnostic_indent += 1
 
-    // Get the "base" command, e.g. gcc.1.gz -> gcc
-    // These casts are safe as OsStr is internally a wrapper around [u8] on all
-    // platforms. Taken from libstd.
-    let cmdname = manpage_path
-        .file_name()
-        .and_then(|file| file.as_bytes().splitn(2, |b| *b == b'.').next());
-    let cmdname = String::from_utf8_lossy(cmdname.unwrap());
+    let stem = manpage_stem(manpage_path);
     let ignored_commands = [
         "cc", "g++", "gcc", "c++", "cpp", "emacs", "gprof", "wget", "ld", "awk",
     ];
-    if ignored_commands.contains(&cmdname.as_ref()) {
-        return Ok(false);
-    }
 
-    let mut manpage = String::new();
+    let mut manpage = Vec::new();
     let extension = manpage_path.extension().unwrap_or_default();
     let extension = extension.to_string_lossy();
     if extension.as_ref() == "gz" {
         let mut gz = GzDecoder::new(File::open(manpage_path)?);
-        gz.read_to_string(&mut manpage)?;
+        gz.read_to_end(&mut manpage)?;
     } else if extension.as_ref() == "bz2" {
         let mut bz = BzDecoder::new(File::open(manpage_path)?);
-        bz.read_to_string(&mut manpage)?;
+        bz.read_to_end(&mut manpage)?;
     } else if extension.as_ref() == "xz" || extension.as_ref() == "lzma" {
         let mut xz = XzDecoder::new(File::open(manpage_path)?);
-        xz.read_to_string(&mut manpage)?;
+        xz.read_to_end(&mut manpage)?;
     } else if (1..=9).any(|suffix| suffix.to_string() == extension.as_ref()) {
-        File::open(manpage_path)?.read_to_string(&mut manpage)?;
+        File::open(manpage_path)?.read_to_end(&mut manpage)?;
+    }
+    let manpage = lossy_unicode(manpage);
+
+    // Get the "base" command, e.g. gcc.1.gz -> gcc, unless the page says
+    // it is for another one
+    let metadata = metadata::PageMetadata::from_manpage(&manpage);
+    // A link is named for the command, whatever the page it leads to says
+    let is_link = matches!(fs::symlink_metadata(manpage_path), Ok(m) if m.file_type().is_symlink());
+    let cmdname = if is_link {
+        stem.clone()
+    } else {
+        metadata.command_name(&stem)
+    };
+    if let Some(description_index) = description_index {
+        description_index.add(&metadata, &cmdname);
+    }
+    // Ignored commands are still described
+    if ignored_commands.contains(&stem.as_ref()) || ignored_commands.contains(&cmdname.as_ref()) {
+        return Ok(false);
+    }
+
+    // A page listing several commands is for them only if they are variants
+    // of one command, unlike openssl-cmds(1ssl), whose links are named for them
+    let page_stem = match is_link {
+        true => {
+            fs::canonicalize(manpage_path).map_or_else(|_| stem.clone(), |path| manpage_stem(&path))
+        }
+        false => stem.clone(),
+    };
+    let is_variants_page = is_variants_page(&metadata, &page_stem);
+    if metadata.names.len() > 1 && !is_variants_page && metadata.names.contains(&cmdname) {
+        return Ok(false);
     }
 
-    // Ignore perl's gazillion man pages
-    let ignored_prefixes = ["perl", "zsh"];
+    let ignored_prefixes = ["zsh"];
     if ignored_prefixes
         .iter()
         .any(|prefix| cmdname.starts_with(prefix))
@@ -1352,12 +2900,17 @@ fn parse_manpage_at_path(
         return Ok(false);
     }
 
+    // Ignore perl's gazillion man pages, but for those of its commands
+    if cmdname.starts_with("perl") && !TypePod2man::has_options_section(&manpage) {
+        return Ok(false);
+    }
+
     // Ignore the millions of links to BUILTIN(1)
     if manpage.contains("BUILTIN 1") || manpage.contains("builtin.1") {
         return Ok(false);
     }
 
-    let parsers = if deroff_only {
+    let parsers = if deroff_only.is_some() {
         &[ManType::TypeDeroff(TypeDeroff)]
     } else {
         ManType::ALL
@@ -1369,31 +2922,68 @@ fn parse_manpage_at_path(
         // add_diagnostic(format!("{}: Not supported", manpage_path));
     }
 
-    if let Some(mut completions) = parsers
-        // .inspect(|parser| add_diagnostic(format!("Trying {}", parser)))
-        .find_map(|parser| parser.parse_man_page(&manpage, &cmdname))
-    {
-        let comments = format!(
-            "# {}\n# Autogenerated from man page {}\n",
-            &cmdname,
-            manpage_path.display()
-        );
-        completions.insert_str(0, &comments);
-
-        if let Some(output_directory) = output_directory {
-            let fullpath = output_directory
-                .join(cmdname.as_ref())
-                .with_extension("fish");
-            match File::create(fullpath) {
-                Ok(mut file) => file.write_all(completions.as_bytes())?,
-                Err(err) => {
-                    // add_diagnostic(format!("Unable to open file '{}': error({}): {}",
-                    // fullpath, errno, strerror));
-                    return Err(err);
-                }
+    let completions = match deroff_only {
+        Some(options) => TypeDeroff.parse_with_options(&manpage, options.clone()),
+        None => {
+            let completions = parsers
+                .by_ref()
+                // .inspect(|parser| add_diagnostic(format!("Trying {}", parser)))
+                .find_map(|parser| parser.parse_man_page(&manpage));
+            // Some options, like those of tr's `[-Ccsu]`, are only in SYNOPSIS
+            let mut completions = completions.unwrap_or_else(Completions::new);
+            if let Some(synopsis) = synopsis::Synopsis::from_manpage(&manpage) {
+                completions.add_synopsis(synopsis);
             }
+            completions.build()
+        }
+    };
+    if let Some(mut completions) = completions {
+        completions.set_placeholders(placeholders.clone());
+        // Pages like gzip(1) are for each of the commands in their NAME line
+        let commands: Vec<&str> = if is_variants_page && metadata.names.contains(&cmdname) {
+            metadata
+                .names
+                .iter()
+                .map(String::as_str)
+                .filter(|name| !ignored_commands.contains(name) && !name.contains('/'))
+                .unique()
+                .collect()
         } else {
-            io::stdout().lock().write_all(completions.as_bytes())?;
+            vec![&cmdname]
+        };
+
+        let mut stdout_separator = "";
+        for (command, completions) in commands.iter().zip(completions.to_fish_for_each(&commands)) {
+            let mut comments = format!(
+                "# {}\n# Autogenerated from man page {}\n",
+                command,
+                manpage_path.display()
+            );
+            if let Some(date) = &metadata.date {
+                comments.push_str(&format!("# Date: {}\n", date));
+            }
+            if let Some(source) = &metadata.source {
+                comments.push_str(&format!("# Source: {}\n", source));
+            }
+            let completions = comments + &completions;
+
+            if let Some(output_directory) = output_directory {
+                // Not `with_extension`, which would replace the `.ext4` of `mkfs.ext4`
+                let fullpath = output_directory.join(format!("{}.fish", command));
+                match File::create(fullpath) {
+                    Ok(mut file) => file.write_all(completions.as_bytes())?,
+                    Err(err) => {
+                        // add_diagnostic(format!("Unable to open file '{}': error({}): {}",
+                        // fullpath, errno, strerror));
+                        return Err(err);
+                    }
+                }
+            } else {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(stdout_separator.as_bytes())?;
+                stdout.write_all(completions.as_bytes())?;
+                stdout_separator = "\n";
+            }
         }
         // add_diagnostic(format!("{} parsed successfully", manpage_path))
         Ok(true)
@@ -1406,6 +2996,112 @@ fn parse_manpage_at_path(
     }
 }
 
+/// Whether the commands in the NAME line of a page are variants of the one
+/// it is for, like gzip(1) with `gunzip` and `zcat`: the page is named for
+/// one of them, and they are all plain words.
+fn is_variants_page(metadata: &metadata::PageMetadata, page_stem: &str) -> bool {
+    metadata
+        .names
+        .iter()
+        .any(|name| name == page_stem || name.eq_ignore_ascii_case(&metadata.title))
+        && metadata
+            .names
+            .iter()
+            .all(|name| regex!(r"^[[:alnum:]][\w.+-]*$").is_match(name))
+}
+
+#[test]
+fn test_is_variants_page() {
+    let gzip = metadata::PageMetadata::from_manpage(
+        ".TH GZIP 1\n.SH NAME\ngzip, gunzip, zcat \\- compress or expand files\n",
+    );
+    assert!(is_variants_page(&gzip, "gzip"));
+    assert!(is_variants_page(&gzip, "page"));
+    let commands = metadata::PageMetadata::from_manpage(
+        ".TH OPENSSL-CMDS 1SSL\n.SH NAME\nasn1parse, ca, cmp \\- OpenSSL application commands\n",
+    );
+    assert!(!is_variants_page(&commands, "openssl-cmds"));
+    assert!(!is_variants_page(&commands, "page"));
+    let builtins = metadata::PageMetadata::from_manpage(
+        ".TH BUILTINS 1\n.SH NAME\nbuiltins, :, ., [, alias \\- shell builtins\n",
+    );
+    assert!(!is_variants_page(&builtins, "builtins"));
+}
+
+#[test]
+fn test_parse_manpage_at_path_names() {
+    let test_dir = env::temp_dir().join("fish-manpage-completions-names-test");
+    fs::remove_dir_all(&test_dir).ok();
+    let out_dir = test_dir.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+    let options =
+        ".SH OPTIONS\n.TP\n\\fB\\-v\\fR\nBe verbose.\n.TP\n\\fB\\-q\\fR\nBe quiet.\n.SH BUGS\n";
+    let gzip = format!(
+        ".TH GZIP 1\n.SH NAME\ngzip, gunzip \\- compress files\n{}",
+        options
+    );
+    let commands = format!(
+        ".TH TOOL-CMDS 1\n.SH NAME\nasn1parse, ca, cmp \\- the commands of tool\n{}",
+        options
+    );
+    fs::write(test_dir.join("gzip.1"), gzip).unwrap();
+    fs::write(test_dir.join("tool-cmds.1"), commands).unwrap();
+    std::os::unix::fs::symlink("tool-cmds.1", test_dir.join("cmp.1")).unwrap();
+
+    for page in ["gzip.1", "tool-cmds.1", "cmp.1"] {
+        parse_manpage_at_path(
+            &test_dir.join(page),
+            Some(&out_dir),
+            None,
+            None,
+            &placeholders::Placeholders::default(),
+        )
+        .unwrap();
+    }
+    let written: Vec<_> = fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .sorted()
+        .collect();
+    assert_eq!(written, ["gunzip.fish", "gzip.fish"]);
+
+    fs::remove_dir_all(&test_dir).unwrap();
+}
+
+#[test]
+fn test_parse_manpage_at_path_ignored() {
+    let test_dir = env::temp_dir().join("fish-manpage-completions-ignored-test");
+    fs::remove_dir_all(&test_dir).ok();
+    let out_dir = test_dir.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+    fs::write(
+        test_dir.join("wget.1"),
+        ".TH WGET 1\n.SH NAME\nwget \\- The non-interactive network downloader.\n\
+         .SH OPTIONS\n.TP\n\\fB\\-q\\fR\nBe quiet.\n",
+    )
+    .unwrap();
+
+    let mut index = metadata::DescriptionIndex::default();
+    let written = parse_manpage_at_path(
+        &test_dir.join("wget.1"),
+        Some(&out_dir),
+        None,
+        Some(&mut index),
+        &placeholders::Placeholders::default(),
+    )
+    .unwrap();
+    assert!(!written);
+    assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 0);
+    let mut lines = Vec::new();
+    index.write(&mut lines).unwrap();
+    assert_eq!(
+        String::from_utf8(lines).unwrap(),
+        "wget\tThe non-interactive network downloader.\n"
+    );
+
+    fs::remove_dir_all(&test_dir).unwrap();
+}
+
 /// Get the number of digits in num
 fn num_digits(n: usize) -> usize {
     (1.max(n) as f32).log10() as usize + 1
@@ -1424,7 +3120,9 @@ fn parse_and_output_man_pages(
     paths: &mut [PathBuf],
     output_directory: Option<PathBuf>,
     show_progress: bool,
-    deroff_only: bool,
+    deroff_only: Option<deroff::DeroffOptions>,
+    mut description_index: Option<&mut metadata::DescriptionIndex>,
+    placeholders: &placeholders::Placeholders,
 ) {
     paths.sort();
 
@@ -1470,7 +3168,13 @@ fn parse_and_output_man_pages(
             lock.flush().expect("Failed to flush stdout");
         }
 
-        match parse_manpage_at_path(&manpage_path, output_directory.as_deref(), deroff_only) {
+        match parse_manpage_at_path(
+            &manpage_path,
+            output_directory.as_deref(),
+            deroff_only.as_ref(),
+            description_index.as_deref_mut(),
+            placeholders,
+        ) {
             Ok(true) => successful_count += 1,
             Ok(false) => {}
             Err(_) => {
@@ -1522,16 +3226,29 @@ macro_rules! mantypes {
                 )*}
             }
 
-            fn parse_man_page(&self, manpage: &str, cmdname: &str) -> Option<String> {
+            fn parse_man_page(&self, manpage: &str) -> Option<Completions> {
                 match self {$(
-                    ManType::$typ(t) => t.parse_man_page(manpage, cmdname),
+                    ManType::$typ(t) => t.parse_man_page(manpage),
                 )*}
             }
         }
     };
 }
 
-mantypes![Type1, Type2, Type3, Type4, TypeScdoc, TypeDarwin, TypeDeroff];
+mantypes![
+    TypeHelp2man,
+    TypePod2man,
+    TypeMarkdown,
+    TypeRst,
+    TypeDocbook,
+    Type1,
+    Type2,
+    Type3,
+    Type4,
+    TypeScdoc,
+    TypeDarwin,
+    TypeDeroff
+];
 
 /// Return all the paths to man(1) and man(8) files in the manpath.
 fn get_paths_from_man_locations() -> Vec<PathBuf> {
@@ -1576,6 +3293,9 @@ fn get_paths_from_man_locations() -> Vec<PathBuf> {
 }
 
 mod deroff;
+mod metadata;
+mod placeholders;
+mod synopsis;
 
 /// Generate fish completions from manpages.
 #[derive(StructOpt, Debug)]
@@ -1589,6 +3309,18 @@ struct Opts {
     /// Use deroff parser only.
     #[structopt(short = "z", long)]
     deroff_only: bool,
+    /// Leave out section titles, with --deroff-only.
+    #[structopt(long, requires = "deroff-only")]
+    skip_headers: bool,
+    /// Keep the SYNOPSIS section, with --deroff-only.
+    #[structopt(long, requires = "deroff-only")]
+    keep_synopsis: bool,
+    /// Keep the text of lists, with --deroff-only.
+    #[structopt(long, requires = "deroff-only")]
+    keep_lists: bool,
+    /// Keep runs of blank lines, with --deroff-only.
+    #[structopt(long, requires = "deroff-only")]
+    no_collapse_blank_lines: bool,
     /// Directory to save the completions in.
     #[structopt(short, long)]
     directory: Option<PathBuf>,
@@ -1604,6 +3336,13 @@ struct Opts {
     /// Keep files in target directory.
     #[structopt(short, long)]
     keep: bool,
+    /// Also write each command and its description, one per line, to this file.
+    #[structopt(long)]
+    description_index: Option<PathBuf>,
+    /// Complete the arguments of options with the `complete` arguments for
+    /// their placeholder in this file, one `PLACEHOLDER ARGUMENTS...` per line.
+    #[structopt(long)]
+    placeholders: Option<PathBuf>,
     /// Generate fish completions.
     // TODO generate this in build.rs and remove this option
     #[structopt(long)]
@@ -1678,13 +3417,44 @@ fn main() -> Result<(), String> {
         }
     }
 
+    let deroff_only = if opts.deroff_only {
+        Some(
+            deroff::DeroffOptions::new()
+                .skip_headers(opts.skip_headers)
+                .keep_synopsis(opts.keep_synopsis)
+                .keep_lists(opts.keep_lists)
+                .collapse_blank_lines(!opts.no_collapse_blank_lines),
+        )
+    } else {
+        None
+    };
+
+    let placeholders = match &opts.placeholders {
+        Some(path) => File::open(path)
+            .and_then(|file| placeholders::Placeholders::read(BufReader::new(file)))
+            .map_err(|err| format!("Unable to read {}: {}", path.display(), err))?,
+        None => placeholders::Placeholders::default(),
+    };
+
+    let mut description_index = opts
+        .description_index
+        .as_ref()
+        .map(|_| metadata::DescriptionIndex::default());
     parse_and_output_man_pages(
         &mut paths,
         output_directory,
         opts.progress,
-        opts.deroff_only,
+        deroff_only,
+        description_index.as_mut(),
+        &placeholders,
     );
 
+    if let (Some(path), Some(description_index)) = (&opts.description_index, description_index) {
+        File::create(path)
+            .and_then(|file| description_index.write(io::BufWriter::new(file)))
+            .map_err(|err| format!("Unable to write {}: {}", path.display(), err))?;
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use itertools::Itertools;
+
+use crate::deroff::Deroffer;
+
+/// What a man page says about itself, in its `.TH` line and NAME section, or
+/// their mdoc counterparts `.Dt`, `.Dd`, `.Os`, `.Nm` and `.Nd`.
+#[derive(Debug, Default, PartialEq)]
+pub struct PageMetadata {
+    pub title: String,
+    pub section: String,
+    pub date: Option<String>,
+    /// Where the command comes from, often its package and version
+    pub source: Option<String>,
+    pub manual: Option<String>,
+    /// The commands documented, e.g. `gzip`, `gunzip` and `zcat`
+    pub names: Vec<String>,
+    pub description: String,
+}
+
+impl PageMetadata {
+    pub fn from_manpage(manpage: &str) -> PageMetadata {
+        let mut metadata = PageMetadata::default();
+        // The NAME section comes first, though not every page calls it that
+        let mut in_name = false;
+        let mut name_text = Vec::new();
+
+        for line in manpage.lines() {
+            let request = line
+                .strip_prefix('.')
+                .or_else(|| line.strip_prefix('\''))
+                .map(|request| {
+                    let request = request.trim_start();
+                    let end = request.find(char::is_whitespace).unwrap_or(request.len());
+                    let args = request[end..].split("\\\"").next().unwrap_or_default();
+                    (&request[..end], Deroffer::macro_args(args))
+                });
+            match request {
+                Some((comment, _)) if comment.starts_with("\\\"") => (),
+                Some(("TH", args)) => {
+                    let mut args = args.into_iter().map(|arg| plain_text(&arg));
+                    metadata.title = args.next().unwrap_or_default();
+                    metadata.section = args.next().unwrap_or_default();
+                    metadata.date = args.next().filter(|arg| !arg.is_empty());
+                    metadata.source = args.next().filter(|arg| !arg.is_empty());
+                    metadata.manual = args.next().filter(|arg| !arg.is_empty());
+                }
+                Some(("Dt", args)) => {
+                    let mut args = args.into_iter();
+                    metadata.title = args.next().unwrap_or_default();
+                    metadata.section = args.next().unwrap_or_default();
+                }
+                Some(("Dd", args)) => {
+                    let date = args.join(" ");
+                    let date = date.strip_prefix("$Mdocdate:").unwrap_or(&date);
+                    let date = date.trim_end_matches('$').trim();
+                    metadata.date = Some(date.to_owned()).filter(|date| !date.is_empty());
+                }
+                Some(("Os", args)) => {
+                    metadata.source = Some(args.join(" ")).filter(|os| !os.is_empty());
+                }
+                Some(("SH" | "Sh", _)) if in_name => break,
+                Some(("SH" | "Sh", _)) => in_name = true,
+                Some(("Nm", args)) if in_name => {
+                    let names = args.into_iter().filter(|arg| arg != ",");
+                    metadata
+                        .names
+                        .extend(names.map(|name| name.trim_end_matches(',').to_owned()));
+                }
+                Some(("Nd", args)) if in_name => metadata.description = plain_text(&args.join(" ")),
+                // The text of the NAME section may be set in a font
+                Some((_, args)) if in_name => name_text.push(args.join(" ")),
+                None if in_name => name_text.push(line.to_owned()),
+                _ => (),
+            }
+        }
+
+        if metadata.names.is_empty() {
+            let name_text = plain_text(&name_text.join(" "));
+            if let Some((names, description)) = name_text.split_once(" - ") {
+                metadata.names = names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                metadata.description = description.trim().to_owned();
+            }
+        }
+        metadata
+    }
+
+    /// The command the page is for. That is `stem`, the file name, if it
+    /// names one of the commands in NAME (or the title, lacking those) or a
+    /// variant of it like `x86_64-linux-gnu-readelf` or `xml2-config`, and
+    /// otherwise the first command in NAME.
+    pub fn command_name(&self, stem: &str) -> String {
+        let title = self.title.to_lowercase();
+        let names = if self.names.is_empty() {
+            vec![title.as_str()]
+        } else {
+            self.names.iter().map(String::as_str).collect()
+        };
+        let names: Vec<_> = names
+            .into_iter()
+            .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+            .collect();
+        // Versions make no difference, `python3.11-config` is `python-config`
+        let unversioned = |name: &str| {
+            crate::regex!(r"\d+(\.\d+)*")
+                .replace_all(&name.to_lowercase(), "")
+                .into_owned()
+        };
+        let unversioned_stem = unversioned(stem);
+        // The name is the stem, or a part of it between dots and dashes
+        let stem_is_named = names.iter().map(|name| unversioned(name)).any(|name| {
+            !name.is_empty()
+                && unversioned_stem.match_indices(&name).any(|(idx, _)| {
+                    let before = unversioned_stem[..idx].chars().next_back();
+                    let after = unversioned_stem[idx + name.len()..].chars().next();
+                    [before, after]
+                        .iter()
+                        .all(|c| c.is_none_or(|c| c == '.' || c == '-'))
+                })
+        });
+        // The name becomes that of the completions file
+        match names.first() {
+            Some(first) if !stem_is_named && is_file_name(first) => (*first).to_owned(),
+            _ => stem.to_owned(),
+        }
+    }
+}
+
+/// Whether `name` can name a file of its own in a directory.
+fn is_file_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..") && !name.contains(['/', '\0'])
+}
+
+/// Commands and their descriptions out of NAME sections, for fish to
+/// describe commands without `apropos`.
+#[derive(Debug, Default)]
+pub struct DescriptionIndex {
+    descriptions: BTreeMap<String, String>,
+}
+
+impl DescriptionIndex {
+    /// Add `cmdname`, and the other commands in NAME if it is one of them.
+    /// The first page to describe a command wins.
+    pub fn add(&mut self, metadata: &PageMetadata, cmdname: &str) {
+        if metadata.description.is_empty() {
+            return;
+        }
+        let commands = if metadata.names.iter().any(|name| name == cmdname) {
+            metadata.names.iter().map(String::as_str).collect()
+        } else {
+            vec![cmdname]
+        };
+        for command in commands {
+            self.descriptions
+                .entry(command.to_owned())
+                .or_insert_with(|| metadata.description.clone());
+        }
+    }
+
+    /// Write a line of `command<TAB>description` for each command, in order.
+    pub fn write<W: Write>(&self, mut write: W) -> io::Result<()> {
+        for (command, description) in &self.descriptions {
+            writeln!(write, "{}\t{}", command, description)?;
+        }
+        write.flush()
+    }
+}
+
+/// The text of `s` without escapes, on one line.
+pub fn plain_text(s: &str) -> String {
+    let mut deroffer = Deroffer::new();
+    if deroffer.deroff(s).is_err() {
+        return String::new();
+    }
+    deroffer.get_output().split_whitespace().join(" ")
+}
+
+#[test]
+fn test_page_metadata() {
+    let metadata = PageMetadata::from_manpage(
+        r#".TH "DOCKER" "1" "Feb 2019" "Docker Community" ""
+.nh
+.SH NAME
+.PP
+docker\-rmi \- Remove one or more images
+.SH SYNOPSIS
+"#,
+    );
+    assert_eq!(
+        metadata,
+        PageMetadata {
+            title: "DOCKER".to_owned(),
+            section: "1".to_owned(),
+            date: Some("Feb 2019".to_owned()),
+            source: Some("Docker Community".to_owned()),
+            manual: None,
+            names: vec!["docker-rmi".to_owned()],
+            description: "Remove one or more images".to_owned(),
+        }
+    );
+
+    let metadata = PageMetadata::from_manpage(
+        r#".TH GZIP 1 local
+.SH NOM
+.\" Names then description
+.B gzip, gunzip, zcat
+\- compress or expand files
+.SH SYNOPSIS
+"#,
+    );
+    assert_eq!(metadata.date, Some("local".to_owned()));
+    assert_eq!(metadata.names, ["gzip", "gunzip", "zcat"]);
+    assert_eq!(metadata.description, "compress or expand files");
+}
+
+#[test]
+fn test_command_name() {
+    let metadata = PageMetadata {
+        title: "GZIP".to_owned(),
+        names: vec!["gzip".to_owned(), "gunzip".to_owned(), "zcat".to_owned()],
+        ..PageMetadata::default()
+    };
+    assert_eq!(metadata.command_name("gunzip"), "gunzip");
+    assert_eq!(
+        metadata.command_name("x86_64-linux-gnu-gzip"),
+        "x86_64-linux-gnu-gzip"
+    );
+    assert_eq!(metadata.command_name("page"), "gzip");
+
+    let metadata = PageMetadata {
+        names: vec!["python-config".to_owned()],
+        ..PageMetadata::default()
+    };
+    assert_eq!(
+        metadata.command_name("python3.11-config"),
+        "python3.11-config"
+    );
+
+    let metadata = PageMetadata {
+        title: "MKFS.EXT4".to_owned(),
+        ..PageMetadata::default()
+    };
+    assert_eq!(metadata.command_name("mkfs.ext4"), "mkfs.ext4");
+    assert_eq!(metadata.command_name("page"), "mkfs.ext4");
+    assert_eq!(PageMetadata::default().command_name("page"), "page");
+
+    let metadata = PageMetadata {
+        names: vec!["test".to_owned(), "[".to_owned()],
+        ..PageMetadata::default()
+    };
+    assert_eq!(metadata.command_name("["), "[");
+    assert_eq!(metadata.command_name("test"), "test");
+    assert_eq!(metadata.command_name("bracket"), "test");
+
+    let metadata = PageMetadata {
+        names: vec![".".to_owned(), "source".to_owned()],
+        ..PageMetadata::default()
+    };
+    assert_eq!(metadata.command_name("dot"), "dot");
+    assert_eq!(metadata.command_name("source"), "source");
+
+    for name in ["..", "../../.bashrc", "bin/ls"] {
+        let metadata = PageMetadata {
+            names: vec![name.to_owned()],
+            ..PageMetadata::default()
+        };
+        assert_eq!(metadata.command_name("page"), "page");
+    }
+
+    let metadata = PageMetadata {
+        names: vec!["ls".to_owned()],
+        ..PageMetadata::default()
+    };
+    assert_eq!(metadata.command_name("false"), "ls");
+    assert_eq!(metadata.command_name("ls-l"), "ls-l");
+}
+
+#[test]
+fn test_description_index() {
+    let mut index = DescriptionIndex::default();
+    let gzip = PageMetadata {
+        names: vec!["gzip".to_owned(), "gunzip".to_owned()],
+        description: "compress or expand files".to_owned(),
+        ..PageMetadata::default()
+    };
+    index.add(&gzip, "gzip");
+    index.add(&gzip, "uncompress");
+    let ls = PageMetadata {
+        names: vec!["ls".to_owned()],
+        description: "list directory contents".to_owned(),
+        ..PageMetadata::default()
+    };
+    index.add(&ls, "ls");
+    index.add(&PageMetadata::default(), "nothing");
+    let gunzip = PageMetadata {
+        names: vec!["gunzip".to_owned()],
+        description: "expand files".to_owned(),
+        ..PageMetadata::default()
+    };
+    index.add(&gunzip, "gunzip");
+
+    let mut output = Vec::new();
+    index.write(&mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "gunzip\tcompress or expand files\n\
+         gzip\tcompress or expand files\n\
+         ls\tlist directory contents\n\
+         uncompress\tcompress or expand files\n"
+    );
+}
+
+#[test]
+fn test_page_metadata_mdoc() {
+    let metadata = PageMetadata::from_manpage(
+        r#".Dd $Mdocdate: June 17 2010 $
+.Dt SSH-COPY-ID 1
+.Os OpenBSD 7.0
+.Sh NAME
+.Nm ssh-copy-id ,
+.Nm ssh-add
+.Nd use locally available keys to authorise logins
+.Sh SYNOPSIS
+.Nm
+"#,
+    );
+    assert_eq!(
+        metadata,
+        PageMetadata {
+            title: "SSH-COPY-ID".to_owned(),
+            section: "1".to_owned(),
+            date: Some("June 17 2010".to_owned()),
+            source: Some("OpenBSD 7.0".to_owned()),
+            manual: None,
+            names: vec!["ssh-copy-id".to_owned(), "ssh-add".to_owned()],
+            description: "use locally available keys to authorise logins".to_owned(),
+        }
+    );
+}
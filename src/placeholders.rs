@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+/// What fish completes the argument of an option to, by its placeholder, like
+/// the users for `--owner=USER`. A file can override the built-in ones.
+#[derive(Clone, Debug, Default)]
+pub struct Placeholders {
+    overrides: HashMap<String, String>,
+}
+
+impl Placeholders {
+    /// Read lines of `PLACEHOLDER ARGUMENTS...`, like
+    /// `keyid -f -a '(__fish_complete_gpg_key_id gpg)'`. A placeholder
+    /// without arguments takes any argument. Blank lines and those that start
+    /// with `#` are ignored.
+    pub fn read<R: BufRead>(read: R) -> io::Result<Placeholders> {
+        let mut placeholders = Placeholders::default();
+        for line in read.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (placeholder, arguments) =
+                line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            placeholders
+                .overrides
+                .insert(key(placeholder), arguments.trim().to_owned());
+        }
+        Ok(placeholders)
+    }
+
+    /// The arguments of `complete` for an option that takes `placeholder`,
+    /// or for the last word of it, e.g. `file` for `config-file`.
+    pub fn arguments(&self, placeholder: &str) -> Option<&str> {
+        let last_word = placeholder
+            .split(|c: char| !c.is_ascii_alphabetic())
+            .rfind(|word| !word.is_empty())?;
+        [key(placeholder), key(last_word)]
+            .iter()
+            .find_map(|key| {
+                self.overrides
+                    .get(key)
+                    .map(String::as_str)
+                    .or_else(|| builtin(key))
+            })
+            .filter(|arguments| !arguments.is_empty())
+    }
+}
+
+/// `user_name` and `USER-NAME` are both `username`, and `string1` is `string`.
+fn key(placeholder: &str) -> String {
+    placeholder
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn builtin(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "file" | "filename" | "path" | "pathname" => "-F",
+        "dir" | "directory" | "dirname" | "folder" => "-f -a '(__fish_complete_directories)'",
+        "user" | "username" | "login" => "-f -a '(__fish_complete_users)'",
+        "group" | "groupname" => "-f -a '(__fish_complete_groups)'",
+        "host" | "hostname" => "-f -a '(__fish_print_hostnames)'",
+        "pid" | "processid" => "-f -a '(__fish_complete_pids)'",
+        "port" | "string" | "number" | "num" | "count" | "seconds" | "value" | "text"
+        | "word" => "-f",
+        "signal" | "sig" | "signum" => {
+            "-f -a 'HUP INT QUIT ILL TRAP ABRT BUS FPE KILL USR1 SEGV USR2 PIPE ALRM TERM CHLD CONT STOP TSTP TTIN TTOU'"
+        }
+        "interface" | "iface" | "ifname" => "-f -a '(__fish_print_interfaces)'",
+        "locale" => "-f -a '(locale -a)'",
+        "encoding" | "charset" | "codeset" => "-f -a '(__fish_print_encodings)'",
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_placeholders() {
+    let placeholders = Placeholders::default();
+    assert_eq!(placeholders.arguments("FILE"), Some("-F"));
+    assert_eq!(placeholders.arguments("config-file"), Some("-F"));
+    assert_eq!(placeholders.arguments("profile"), None);
+    assert_eq!(
+        placeholders.arguments("<user_name>"),
+        Some("-f -a '(__fish_complete_users)'")
+    );
+    assert_eq!(placeholders.arguments("WHEN"), None);
+    assert_eq!(placeholders.arguments("..."), None);
+
+    let placeholders = Placeholders::read(
+        "# gpg\nKEYID -f -a '(__fish_complete_gpg_key_id gpg)'\n\nhost\n".as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(
+        placeholders.arguments("key-id"),
+        Some("-f -a '(__fish_complete_gpg_key_id gpg)'")
+    );
+    assert_eq!(placeholders.arguments("HOST"), None);
+    assert_eq!(
+        placeholders.arguments("DIR"),
+        Some("-f -a '(__fish_complete_directories)'")
+    );
+}
@@ -0,0 +1,244 @@
+use crate::deroff::{font_spans, DeroffOptions, Deroffer, Font};
+
+/// An option as the SYNOPSIS section shows it, like the `-f file` of
+/// `[-f file]`.
+#[derive(Debug, PartialEq)]
+pub struct SynopsisOption {
+    pub name: String,
+    pub argument: Option<String>,
+}
+
+impl SynopsisOption {
+    /// Whether this is an old style option, like the `-display name` of X11,
+    /// since a group of short options takes no argument.
+    pub fn is_old_style(&self) -> bool {
+        let name = self.name.strip_prefix('-').unwrap_or("-");
+        !name.starts_with('-') && name.len() >= 2 && self.argument.is_some()
+    }
+
+    /// The short options of a group like `-Ccsu` or `+Ccsu`, if this could
+    /// be one.
+    pub fn bundled(&self) -> Option<Vec<String>> {
+        let prefix = self.name.chars().next()?;
+        let letters = self.name.strip_prefix(['-', '+'])?;
+        if self.argument.is_some()
+            || letters.len() < 2
+            || !letters.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        Some(
+            letters
+                .chars()
+                .map(|c| format!("{}{}", prefix, c))
+                .collect(),
+        )
+    }
+}
+
+/// The options and operands in the SYNOPSIS section of a page.
+#[derive(Debug, Default, PartialEq)]
+pub struct Synopsis {
+    pub options: Vec<SynopsisOption>,
+    /// The placeholders for the operands, e.g. `string1` and `string2`
+    pub operands: Vec<String>,
+}
+
+impl Synopsis {
+    /// The SYNOPSIS section of `manpage`, if it has one. Only the options in
+    /// brackets take the word after them as their argument, since the
+    /// operands of the command follow the others.
+    pub fn from_manpage(manpage: &str) -> Option<Synopsis> {
+        let text = synopsis_text(manpage)?;
+        // `--[no-]tags` is both `--tags` and `--no-tags`
+        let text = crate::regex!(r"--\[no(-?)\]([\w-]+)").replace_all(&text, "--$2 --no$1$2");
+        let option_re = crate::regex!(
+            r"^((?:--?[[:alnum:]]|\+[[:lower:]\d])[\w.+-]*?)(?:(\[?)=([^\s\[\]]+)\]?)?[,.]*$"
+        );
+        // Brackets and bars are tokens of their own, but for those of `--color[=WHEN]`
+        let mut tokens = crate::regex!(r"[^\s\[\]|]+(\[=[^\s\]]*\])?|[\[\]|]")
+            .find_iter(&text)
+            .map(|m| m.as_str())
+            .peekable();
+        // The command heads each of its forms
+        let command = tokens
+            .peek()
+            .copied()
+            .filter(|token| !token.starts_with(['-', '[', '|']));
+        let mut depth = 0;
+        let mut synopsis = Synopsis::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                "[" => depth += 1,
+                "]" => depth -= 1,
+                _ => (),
+            }
+            let caps = match option_re.captures(token) {
+                Some(caps) => caps,
+                None => {
+                    let operand = token.trim_end_matches(['.', ',']).trim_matches(['<', '>']);
+                    let is_option_placeholder = operand.to_lowercase().starts_with("option");
+                    if !operand.is_empty()
+                        && operand.chars().any(char::is_alphanumeric)
+                        && Some(token) != command
+                        && !is_option_placeholder
+                        && !synopsis.operands.iter().any(|known| known == operand)
+                    {
+                        synopsis.operands.push(operand.to_owned());
+                    }
+                    continue;
+                }
+            };
+            let argument = match caps.get(3) {
+                Some(argument) if !caps[2].is_empty() => Some(format!("[{}]", argument.as_str())),
+                Some(argument) => Some(argument.as_str().to_owned()),
+                None if depth > 0 => tokens
+                    .next_if(|token| {
+                        !["[", "]", "|"].contains(token)
+                            && !token.starts_with('-')
+                            && !token.starts_with("...")
+                    })
+                    .map(|argument| argument.trim_matches(['<', '>']).to_owned()),
+                None => None,
+            };
+            match synopsis
+                .options
+                .iter_mut()
+                .find(|option| option.name == caps[1])
+            {
+                Some(option) => {
+                    option.argument = option.argument.take().or(argument);
+                }
+                None => synopsis.options.push(SynopsisOption {
+                    name: caps[1].to_owned(),
+                    argument,
+                }),
+            }
+        }
+        Some(synopsis)
+    }
+}
+
+/// The text of the SYNOPSIS section, without its title. An argument in
+/// italics right after an option, like the `level` of `-O\fIlevel\fR`, is
+/// set off as in `-O=level`, rather than look like a group of options.
+fn synopsis_text(manpage: &str) -> Option<String> {
+    let mut lines = manpage
+        .lines()
+        .skip_while(|line| !crate::regex!(r#"^\.S[Hh]\s+"?SYNOPSIS"#).is_match(line));
+    lines.next()?;
+    let section: Vec<_> = lines
+        .take_while(|line| !crate::regex!(r"^\.S[Hh]\b").is_match(line))
+        .collect();
+    let section = section.join("\n") + "\n";
+    let mut deroffer = Deroffer::with_options(DeroffOptions::new().font_annotations(true));
+    deroffer.deroff(&section).ok()?;
+    let mut text = String::new();
+    for (font, span) in font_spans(&deroffer.get_output()) {
+        let word = text
+            .rsplit(|c: char| c.is_whitespace() || "[]|".contains(c))
+            .next()
+            .unwrap_or_default();
+        if matches!(font, Font::Italic | Font::BoldItalic)
+            && word.len() > 1
+            && word.starts_with(['-', '+'])
+            && !word.ends_with('=')
+            && span.starts_with(char::is_alphanumeric)
+        {
+            text.push('=');
+        }
+        text.push_str(&span);
+    }
+    Some(text)
+}
+
+#[test]
+fn test_synopsis() {
+    let option = |name: &str, argument: Option<&str>| SynopsisOption {
+        name: name.to_owned(),
+        argument: argument.map(str::to_owned),
+    };
+    let manpage = r#".TH TR 1
+.SH SYNOPSIS
+\fBtr\fR [\fB\-Ccsu\fR] \fIstring1 string2\fR
+.br
+\fBtr\fR \-d [\fB\-f\fR \fIfile\fR | \fB\-\-color\fR[=\fIWHEN\fR]] \fIstring1\fR
+.br
+\fBtr\fR [\fB\-\-output\fR=\fIFILE\fR] [\fB\-C\fR] [\fB\-\-[no\-]tags\fR] [\fIfile\fR ...]
+.SH DESCRIPTION
+[\-x]
+"#;
+    assert_eq!(
+        Synopsis::from_manpage(manpage),
+        Some(Synopsis {
+            options: vec![
+                option("-Ccsu", None),
+                option("-d", None),
+                option("-f", Some("file")),
+                option("--color", Some("[WHEN]")),
+                option("--output", Some("FILE")),
+                option("-C", None),
+                option("--tags", None),
+                option("--no-tags", None),
+            ],
+            operands: vec![
+                "string1".to_owned(),
+                "string2".to_owned(),
+                "file".to_owned()
+            ],
+        })
+    );
+    let manpage =
+        ".SH SYNOPSIS\n\\fBfind\\fR [\\-H] [\\-O\\fIlevel\\fR] [\\fIpath\\fR...]\n.SH OPTIONS\n";
+    assert_eq!(
+        Synopsis::from_manpage(manpage),
+        Some(Synopsis {
+            options: vec![option("-H", None), option("-O", Some("level"))],
+            operands: vec!["path".to_owned()],
+        })
+    );
+    assert_eq!(
+        option("-Ccsu", None).bundled(),
+        Some(vec![
+            "-C".to_owned(),
+            "-c".to_owned(),
+            "-s".to_owned(),
+            "-u".to_owned()
+        ])
+    );
+    assert_eq!(option("-f", Some("file")).bundled(), None);
+    assert_eq!(
+        option("+ex", None).bundled(),
+        Some(vec!["+e".to_owned(), "+x".to_owned()])
+    );
+    assert!(option("-display", Some("name")).is_old_style());
+    assert!(!option("-f", Some("file")).is_old_style());
+    assert!(!option("-Ccsu", None).is_old_style());
+
+    let manpage = r#".Dt TR 1
+.Sh SYNOPSIS
+.Nm
+.Op Fl Ccsu
+.Ar string1 string2
+.Nm
+.Op Fl o Ar file
+.Fl d
+.Ar string1
+.Sh DESCRIPTION
+"#;
+    assert_eq!(
+        Synopsis::from_manpage(manpage),
+        Some(Synopsis {
+            options: vec![
+                option("-Ccsu", None),
+                option("-o", Some("file")),
+                option("-d", None)
+            ],
+            operands: vec!["string1".to_owned(), "string2".to_owned()],
+        })
+    );
+
+    let manpage = ".SH SYNOPSIS\nwhoami [OPTION]...\n";
+    assert_eq!(Synopsis::from_manpage(manpage), Some(Synopsis::default()));
+    assert_eq!(Synopsis::from_manpage(".SH DESCRIPTION\n[-x]\n"), None);
+}